@@ -1,3 +1,4 @@
+use crate::migrations::upgrade_account;
 use crate::*;
 use near_sdk::log;
 
@@ -7,7 +8,10 @@ pub use crate::utils::*;
 // -----------------
 // User Account Data
 // -----------------
-#[derive(BorshDeserialize, BorshSerialize, Debug, PartialEq)]
+// Stored as version tag (ACCOUNT_LAYOUT_VERSION), fields, ACCOUNT_LAYOUT_MARKER, see impl BorshSerialize.
+// Accounts stored in an older layout are converted when read (first touch),
+// and written in the current layout on the next save or by migrate_accounts
+#[derive(Debug)]
 pub struct Account {
     /// This amount increments with deposits and decrements with for_staking
     /// increments with complete_unstake and decrements with user withdrawals from the contract
@@ -32,17 +36,8 @@ pub struct Account {
     /// The funds will be locked for -AT LEAST- NUM_EPOCHS_TO_UNLOCK epochs
     pub unstaked_requested_unlock_epoch: EpochHeight,
 
-    //-- META (now mpDAO) INCENTIVES (Disabled on 2023-05)
-    // realized META, can be used to transfer META from one user to another
-    // Total META = realized_meta + staking_meter.mul_rewards(valued_stake_shares) + lp_meter.mul_rewards(valued_lp_shares)
-    // Every time the user operates on STAKE/UNSTAKE: we realize meta: realized_meta += staking_meter.mul_rewards(valued_staked_shares)
-    // Every time the user operates on ADD.LIQ/REM.LIQ.: we realize meta: realized_meta += lp_meter.mul_rewards(valued_lp_shares)
-    // if the user calls farm_meta() we perform both
-    pub realized_meta: u128, // deprecated
     ///Staking rewards meter (to mint stNEAR for the user)
     pub staking_meter: RewardMeter,
-    ///LP fee gains meter (to mint meta for the user)
-    pub lp_meter: RewardMeter, // deprecated
 
     //-- STATISTICAL DATA --
     // User's statistical data
@@ -61,6 +56,101 @@ pub struct Account {
 
     ///NS liquidity pool shares, if the user is a liquidity provider
     pub nslp_shares: u128,
+    /// NEAR added to the NSLP for the current nslp_shares, reduced proportionally when shares are removed or transferred.
+    /// 0 for positions opened before it was tracked, see internal_nslp_add_liquidity
+    pub nslp_cost_basis: u128,
+
+    /// (not stored) ACCOUNT_LAYOUT_VERSION this account was read from
+    pub stored_version: u16,
+}
+
+/// last byte of every tagged account layout.
+/// v1 accounts were stored without version tag and end with the most significant byte of nslp_shares,
+/// always 0 (u128 amounts are < 2^120 yoctos), so an account ending with the marker is never v1
+const ACCOUNT_LAYOUT_MARKER: u8 = 0xAC;
+// stored length: v1 180 bytes, v2 183 (v1 + tag & marker), v3 149 (v2 - META fields), v4 165 (v3 + nslp_cost_basis)
+
+impl BorshSerialize for Account {
+    fn serialize<W: std::io::Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        ACCOUNT_LAYOUT_VERSION.serialize(writer)?;
+        self.available.serialize(writer)?;
+        self.stake_shares.serialize(writer)?;
+        self.unstaked.serialize(writer)?;
+        self.unstaked_requested_unlock_epoch.serialize(writer)?;
+        self.staking_meter.serialize(writer)?;
+        self.trip_start.serialize(writer)?;
+        self.trip_start_stnear.serialize(writer)?;
+        self.trip_accum_stakes.serialize(writer)?;
+        self.trip_accum_unstakes.serialize(writer)?;
+        self.nslp_shares.serialize(writer)?;
+        self.nslp_cost_basis.serialize(writer)?;
+        ACCOUNT_LAYOUT_MARKER.serialize(writer)
+    }
+}
+
+impl BorshDeserialize for Account {
+    /// accounts are stored as standalone values (accounts map), buf ends where the account ends
+    fn deserialize(buf: &mut &[u8]) -> std::io::Result<Self> {
+        let tagged = buf.last() == Some(&ACCOUNT_LAYOUT_MARKER);
+        let stored_version = if tagged { u16::deserialize(buf)? } else { 1 };
+        if stored_version == 0 || stored_version > ACCOUNT_LAYOUT_VERSION {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("unknown account layout version {}", stored_version),
+            ));
+        }
+        // v1 & v2 stored the deprecated META fields
+        let with_meta = stored_version <= 2;
+        let available = u128::deserialize(buf)?;
+        let stake_shares = u128::deserialize(buf)?;
+        let unstaked = u128::deserialize(buf)?;
+        let unstaked_requested_unlock_epoch = EpochHeight::deserialize(buf)?;
+        if with_meta {
+            let _realized_meta = u128::deserialize(buf)?;
+        }
+        let staking_meter = RewardMeter::deserialize(buf)?;
+        if with_meta {
+            let _lp_meter = RewardMeter::deserialize(buf)?;
+        }
+        let mut account = Self {
+            available,
+            stake_shares,
+            unstaked,
+            unstaked_requested_unlock_epoch,
+            staking_meter,
+            trip_start: Timestamp::deserialize(buf)?,
+            trip_start_stnear: u128::deserialize(buf)?,
+            trip_accum_stakes: u128::deserialize(buf)?,
+            trip_accum_unstakes: u128::deserialize(buf)?,
+            nslp_shares: u128::deserialize(buf)?,
+            // positions opened before v4 have no cost basis, see is_nslp_cost_basis_tracked
+            nslp_cost_basis: if stored_version >= 4 { u128::deserialize(buf)? } else { 0 },
+            stored_version,
+        };
+        if tagged {
+            u8::deserialize(buf)?; // marker
+        }
+        // apply account migration steps up to STATE_VERSION
+        upgrade_account(&mut account);
+        Ok(account)
+    }
+}
+
+/// stored_version is where the account was read from, not part of its value
+impl PartialEq for Account {
+    fn eq(&self, other: &Self) -> bool {
+        self.available == other.available
+            && self.stake_shares == other.stake_shares
+            && self.unstaked == other.unstaked
+            && self.unstaked_requested_unlock_epoch == other.unstaked_requested_unlock_epoch
+            && self.staking_meter == other.staking_meter
+            && self.trip_start == other.trip_start
+            && self.trip_start_stnear == other.trip_start_stnear
+            && self.trip_accum_stakes == other.trip_accum_stakes
+            && self.trip_accum_unstakes == other.trip_accum_unstakes
+            && self.nslp_shares == other.nslp_shares
+            && self.nslp_cost_basis == other.nslp_cost_basis
+    }
 }
 
 /// User account on this contract
@@ -71,10 +161,8 @@ impl Default for Account {
             stake_shares: 0,
             unstaked: 0,
             unstaked_requested_unlock_epoch: 0,
-            //meta & reward-meters
-            realized_meta: 0,
+            //reward-meter
             staking_meter: RewardMeter::default(),
-            lp_meter: RewardMeter::default(),
             //trip-meter fields
             trip_start: env::block_timestamp() / 1_000_000, //converted from nanoseconds to milliseconds
             trip_start_stnear: 0,
@@ -82,11 +170,18 @@ impl Default for Account {
             trip_accum_unstakes: 0,
             //NS liquidity pool
             nslp_shares: 0,
+            nslp_cost_basis: 0,
+            stored_version: ACCOUNT_LAYOUT_VERSION,
         }
     }
 }
 impl Account {
 
+    /// true if the stored layout is older than the current one
+    pub fn needs_migration(&self) -> bool {
+        self.stored_version < ACCOUNT_LAYOUT_VERSION
+    }
+
     /// if the account can be closed and storage deposit returned
     pub fn can_be_closed(&self) -> bool {
         self.available == 0
@@ -95,9 +190,38 @@ impl Account {
         && self.nslp_shares == 0
     }
 
+    /// positions opened before the cost basis was tracked have shares but no cost basis.
+    /// They stay untracked, see get_lp_position
+    pub fn is_nslp_cost_basis_tracked(&self) -> bool {
+        self.nslp_cost_basis > 0 || self.nslp_shares == 0
+    }
+
+    /// moves nslp shares and their cost basis to `receiver`, the caller checks the balance.
+    /// Untracked shares make the receiver position untracked
+    pub fn move_nslp_shares(&mut self, receiver: &mut Account, shares: u128) {
+        let tracked = self.is_nslp_cost_basis_tracked() && receiver.is_nslp_cost_basis_tracked();
+        let cost_basis = self.nslp_cost_basis_of(shares);
+        self.nslp_cost_basis -= cost_basis;
+        self.nslp_shares -= shares;
+        receiver.nslp_cost_basis = if tracked {
+            receiver.nslp_cost_basis + cost_basis
+        } else {
+            0
+        };
+        receiver.nslp_shares += shares;
+    }
+
+    /// cost basis of nslp_shares_to_remove, proportional to the shares
+    pub fn nslp_cost_basis_of(&self, nslp_shares_to_remove: u128) -> u128 {
+        if self.nslp_shares == 0 {
+            return 0;
+        }
+        proportional(self.nslp_cost_basis, nslp_shares_to_remove, self.nslp_shares)
+    }
+
     #[inline]
     pub fn valued_nslp_shares(&self, main: &MetaPool, nslp_account: &Account) -> u128 {
-        main.amount_from_nslp_shares(self.nslp_shares, &nslp_account)
+        main.amount_from_nslp_shares(self.nslp_shares, nslp_account)
     }
 
     //----------------
@@ -148,7 +272,7 @@ impl Account {
     /// completes unstake action by moving from acc.unstaked & main.retrieved_for_unstaked_claims -> acc.available & main.total_available
     pub fn in_memory_try_finish_unstaking(
         &mut self,
-        account_id: &str,
+        account_id: &AccountId,
         amount: u128,
         main: &mut MetaPool,
     ) -> u128 {
@@ -178,11 +302,18 @@ impl Account {
         main.total_unstake_claims -= amount;
         main.total_available += amount;
 
-        event!(
-            r#"{{"event":"D-WITHD","account_id":"{}","amount":"{}"}}"#,
+        events::UnstakeClaim {
             account_id,
-            amount
-        );
+            amount: amount.into(),
+        }
+        .emit();
+        events::MtMint {
+            owner_id: account_id,
+            token_ids: vec![MT_TOKEN_NEAR],
+            amounts: vec![amount.into()],
+            memo: Some("unstake_claim"),
+        }
+        .emit();
 
         log!("{} unstaked moved to available", amount);
 
@@ -199,7 +330,7 @@ impl Account {
         // if the amount is close to user's total, remove user's total
         // to: a) do not leave less than ONE_MILLI_NEAR in the account, b) Allow some yoctos of rounding, e.g. remove(100) removes 99.999993 without panicking
         // Audit Note: Do not do this for .lockup accounts because the lockup contract relies on precise amounts
-        if is_lockup_account(account_id) || !is_close(amount_requested, self.available) { 
+        if main.is_lockup_account(account_id) || !is_close(amount_requested, self.available) { 
             // exact amount
             amount_requested
         }
@@ -218,6 +349,131 @@ impl Account {
         assert!(main.total_available >= to_withdraw, "i_s_Inconsistency");
         main.total_available -= to_withdraw;
 
+        // leaves the available balance (withdrawn, staked or added to the NSLP)
+        if to_withdraw > 0 {
+            events::MtBurn {
+                owner_id: account_id,
+                token_ids: vec![MT_TOKEN_NEAR],
+                amounts: vec![to_withdraw.into()],
+                memo: None,
+            }
+            .emit();
+        }
+
         return to_withdraw;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_account() -> Account {
+        Account {
+            available: 1,
+            stake_shares: 2 * NEAR,
+            unstaked: 3,
+            unstaked_requested_unlock_epoch: 4,
+            staking_meter: RewardMeter {
+                delta_staked: -5,
+                last_multiplier_pct: 100,
+            },
+            trip_start: 6,
+            trip_start_stnear: 7,
+            trip_accum_stakes: 8,
+            trip_accum_unstakes: 9,
+            nslp_shares: 10 * NEAR,
+            nslp_cost_basis: 0,
+            stored_version: ACCOUNT_LAYOUT_VERSION,
+        }
+    }
+
+    /// bytes as stored in an older layout, see ACCOUNT_LAYOUT_VERSION
+    fn layout_bytes(acc: &Account, layout_version: u16) -> Vec<u8> {
+        let mut bytes = vec![];
+        if layout_version > 1 {
+            layout_version.serialize(&mut bytes).unwrap();
+        }
+        acc.available.serialize(&mut bytes).unwrap();
+        acc.stake_shares.serialize(&mut bytes).unwrap();
+        acc.unstaked.serialize(&mut bytes).unwrap();
+        acc.unstaked_requested_unlock_epoch.serialize(&mut bytes).unwrap();
+        if layout_version <= 2 {
+            11u128.serialize(&mut bytes).unwrap(); // realized_meta
+        }
+        acc.staking_meter.serialize(&mut bytes).unwrap();
+        if layout_version <= 2 {
+            RewardMeter::default().serialize(&mut bytes).unwrap(); // lp_meter
+        }
+        acc.trip_start.serialize(&mut bytes).unwrap();
+        acc.trip_start_stnear.serialize(&mut bytes).unwrap();
+        acc.trip_accum_stakes.serialize(&mut bytes).unwrap();
+        acc.trip_accum_unstakes.serialize(&mut bytes).unwrap();
+        acc.nslp_shares.serialize(&mut bytes).unwrap();
+        if layout_version >= 4 {
+            acc.nslp_cost_basis.serialize(&mut bytes).unwrap();
+        }
+        if layout_version > 1 {
+            ACCOUNT_LAYOUT_MARKER.serialize(&mut bytes).unwrap();
+        }
+        bytes
+    }
+
+    #[test]
+    fn test_current_layout_round_trip() {
+        let mut acc = sample_account();
+        acc.nslp_cost_basis = 12;
+        let bytes = acc.try_to_vec().unwrap();
+        assert_eq!(bytes, layout_bytes(&acc, ACCOUNT_LAYOUT_VERSION));
+        assert_eq!(bytes.len(), 165);
+        assert_eq!(bytes[..2], ACCOUNT_LAYOUT_VERSION.to_le_bytes());
+        assert_eq!(*bytes.last().unwrap(), ACCOUNT_LAYOUT_MARKER);
+        let read = Account::try_from_slice(&bytes).unwrap();
+        assert_eq!(read, acc);
+        assert_eq!(read.stored_version, ACCOUNT_LAYOUT_VERSION);
+        assert!(!read.needs_migration());
+    }
+
+    #[test]
+    fn test_older_layouts_are_converted() {
+        let acc = sample_account();
+        for (layout_version, len) in [(1, 180), (2, 183), (3, 149)].iter() {
+            let bytes = layout_bytes(&acc, *layout_version);
+            assert_eq!(bytes.len(), *len);
+            let read = Account::try_from_slice(&bytes).unwrap();
+            assert_eq!(read, acc);
+            assert_eq!(read.stored_version, *layout_version);
+            assert!(read.needs_migration());
+            // written back in the current layout
+            let rewritten = Account::try_from_slice(&read.try_to_vec().unwrap()).unwrap();
+            assert_eq!(rewritten, acc);
+            assert_eq!(rewritten.stored_version, ACCOUNT_LAYOUT_VERSION);
+        }
+    }
+
+    #[test]
+    fn test_v1_layout_ending_like_a_tag_is_v1() {
+        // nslp_shares low bytes can take any value, only the last byte tells the layouts apart
+        let mut acc = sample_account();
+        acc.nslp_shares = u128::from_le_bytes([ACCOUNT_LAYOUT_MARKER; 16]) >> 8;
+        let read = Account::try_from_slice(&layout_bytes(&acc, 1)).unwrap();
+        assert_eq!(read.stored_version, 1);
+        assert_eq!(read.nslp_shares, acc.nslp_shares);
+    }
+
+    #[test]
+    fn test_unknown_layout_version() {
+        let mut bytes = sample_account().try_to_vec().unwrap();
+        bytes[..2].copy_from_slice(&(ACCOUNT_LAYOUT_VERSION + 1).to_le_bytes());
+        assert!(Account::try_from_slice(&bytes).is_err());
+    }
+
+    #[test]
+    fn test_eq_ignores_stored_version() {
+        let mut other = sample_account();
+        other.stored_version = 1;
+        assert_eq!(sample_account(), other);
+        other.nslp_cost_basis = 1;
+        assert_ne!(sample_account(), other);
+    }
+}
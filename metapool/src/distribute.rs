@@ -122,7 +122,7 @@ impl MetaPool {
                 //schedule async stake or deposit_and_stake on that pool
                 ext_staking_pool::deposit_and_stake(
                     &sp.account_id,
-                    amount_to_stake.into(), //attached amount
+                    amount_to_stake, //attached amount
                     gas::staking_pool::DEPOSIT_AND_STAKE,
                 )
                 .then(ext_self_owner::on_staking_pool_stake_maybe_deposit(
@@ -168,14 +168,12 @@ impl MetaPool {
             // move into staked
             sp.staked += amount;
             // update accums based on the source of the funds
-            let event: &str;
             if included_deposit {
                 // we sent NEAR from the contract into the staking-pool
-                event = "dist.stak"; //stake in the pools (including transfer)
                 self.contract_account_balance -= amount; // we took from contract balance (transfer)
             } else {
                 // stake the unstaked in the pool, no-transfer
-                event = "dist.stak.nt"; //not deposited first, so staked funds came from unstaked funds already in the staking-pool
+                // not deposited first, so staked funds came from unstaked funds already in the staking-pool
                 sp.unstaked -= amount; //we've now less unstaked in this sp
                 self.total_unstaked_and_waiting -= amount; // contract total of all unstaked & waiting, now there's less there.
                                                            // We kept the NEAR in the contract and took from unstaked_and_waiting
@@ -183,13 +181,12 @@ impl MetaPool {
                 self.consider_retrieved_for_unstake_claims(amount); // so this is a special case: the NEAR to stake was taken from total_unstaked_and_waiting,
                                                              // so we compensate and take the NEAR in the contract and consider it reserved for_unstake_claims
             }
-            //log event
-            event!(
-                r#"{{"event":"{}","sp":"{}","amount":"{}"}}"#,
-                event,
-                sp_account_id,
-                amount
-            );
+            events::DistributeStake {
+                sp: &sp_account_id,
+                amount: amount.into(),
+                deposited: included_deposit,
+            }
+            .emit();
         } else {
             //STAKE FAILED
             result = "has failed";
@@ -370,7 +367,7 @@ impl MetaPool {
                 let max_unstake_for_rebalance = self.max_unstake_for_rebalance();
                 if self.unstaked_for_rebalance < max_unstake_for_rebalance {
                     let cap_to_extra_unstake_for_rebalance = max_unstake_for_rebalance - self.unstaked_for_rebalance;
-                    if cap_to_extra_unstake_for_rebalance > 1*NEAR {
+                    if cap_to_extra_unstake_for_rebalance > NEAR {
                         unstake_from_rebalance = std::cmp::min(cap_to_extra_unstake_for_rebalance, gspru.extra - unstake_from_orders);
                     }
                 }
@@ -461,12 +458,12 @@ impl MetaPool {
             sp.unstk_req_epoch_height = env::epoch_height();
             self.total_unstaked_and_waiting += total_amount; // contract total unstaked_and_waiting
             self.unstaked_for_rebalance += amount_from_rebalance.0; // total unstaked_and_waiting for rebalance
-            event!(
-                r#"{{"event":"unstk","sp":"{}","amount_fuo":"{}","amount_fr":"{}" }}"#,
-                sp.account_id,
-                amount_from_unstake_orders.0,
-                amount_from_rebalance.0
-            );
+            events::DistributeUnstake {
+                sp: &sp.account_id,
+                amount_from_unstake_orders,
+                amount_from_rebalance,
+            }
+            .emit();
         } else {
             result = "has failed";
             self.total_actually_staked += total_amount; //undo preventive action considering the amount unstaked
@@ -511,15 +508,21 @@ impl MetaPool {
     }
 
     //-- If extra balance has accumulated (30% of tx fees by near-protocol)
-    // transfer to self.operator_account_id
+    // route it now according to gas_rebate_split (operator, treasury & staking)
+    // Note: it is also routed automatically once per epoch, see internal_end_of_epoch_clearing
     pub fn transfer_extra_balance_accumulated(&mut self) -> U128String {
-        let extra_balance = self.extra_balance_accumulated().0;
-        if extra_balance >= ONE_NEAR {
-            //only if there's more than one near, and left 10 cents (consider transfer fees)
-            Promise::new(self.operator_account_id.clone()).transfer(extra_balance - 10 * NEAR_CENT);
-            return extra_balance.into();
+        self.assert_not_busy();
+        self.internal_route_gas_rebate().into()
+    }
+
+    /// gas rebate split, pending amount & cumulative counter
+    pub fn get_gas_rebate_info(&self) -> GasRebateInfoJSON {
+        GasRebateInfoJSON {
+            split: self.gas_rebate_split,
+            pending: self.internal_gas_rebate_available().into(),
+            last_routed_epoch: self.gas_rebate_last_epoch.into(),
+            total_routed: self.total_gas_rebate_routed.into(),
         }
-        return 0.into();
     }
 
     //-------------------------
@@ -713,8 +716,6 @@ impl MetaPool {
         //we enter here after asking the staking-pool how much do we have staked (plus rewards)
         //total_balance: U128String contains the answer from the staking-pool
 
-        //new_total_balance has the new staked amount for this pool
-        let new_total_balance: u128;
         let sp = &mut self.staking_pools[sp_inx];
 
         //WARN: This is a callback after-cross-contract-call method
@@ -725,19 +726,19 @@ impl MetaPool {
         sp.last_asked_rewards_epoch_height = env::epoch_height();
 
         //total_balance informed is staking-pool.staked + staking-pool.unstaked
-        new_total_balance = total_balance.0;
+        //new_total_balance has the new staked amount for this pool
+        let new_total_balance = total_balance.0;
 
-        let rewards: u128;
-        if new_total_balance < sp.total_balance() {
+        let rewards = if new_total_balance < sp.total_balance() {
             log!(
                 "INCONSISTENCY @{} says new_total_balance < our info sp.total_balance()",
                 sp.account_id
             );
-            rewards = 0;
+            0
         } else {
             //compute rewards, as new balance minus old balance
-            rewards = new_total_balance - sp.total_balance();
-        }
+            new_total_balance - sp.total_balance()
+        };
 
         log!(
             "sp:{} old_balance:{} new_balance:{} rewards:{} unstaked:{}",
@@ -754,21 +755,33 @@ impl MetaPool {
         sp.staked = new_total_balance - sp.unstaked;
 
         if rewards > 0 {
+            events::Rewards {
+                sp: &sp.account_id,
+                rewards: rewards.into(),
+                new_total_balance: new_total_balance.into(),
+            }
+            .emit();
             //add to total_for_staking & total_actually_staked, increasing share value for all stNEAR holders
             self.total_actually_staked += rewards;
             self.total_for_staking += rewards;
 
-            // mint extra stNEAR representing fees for operator & developers
-            // The fee the operator takes from rewards (0.5%)
-            let operator_fee = apply_pct(self.operator_rewards_fee_basis_points, rewards);
-            let operator_fee_shares = self.stake_shares_from_amount(operator_fee);
-            // The fee the contract authors take from rewards (0.2%)
-            let developers_fee = apply_pct(DEVELOPERS_REWARDS_FEE_BASIS_POINTS, rewards);
-            let developers_fee_shares = self.stake_shares_from_amount(developers_fee);
+            // mint extra stNEAR representing fees for the fee beneficiaries
+            // (by default operator 0.5% & developers 0.2% of the rewards)
+            let fee_shares: Vec<(AccountId, u128)> = self
+                .fee_beneficiaries
+                .iter()
+                .map(|beneficiary| {
+                    let fee = apply_pct(beneficiary.rewards_fee_bp, rewards);
+                    (
+                        self.fee_recipient_account_id(&beneficiary.recipient),
+                        self.stake_shares_from_amount(fee),
+                    )
+                })
+                .collect();
             // Now add the newly minted shares. The fee is taken by making share price increase slightly smaller
-            self.add_extra_minted_shares(self.operator_account_id.clone(), operator_fee_shares);
-            self.add_extra_minted_shares(DEVELOPERS_ACCOUNT_ID.into(), developers_fee_shares);
-
+            for (account_id, shares) in fee_shares {
+                self.add_extra_minted_shares(account_id, shares);
+            }
         }
     }
 
@@ -875,12 +888,11 @@ impl MetaPool {
             self.total_unstaked_and_waiting = // contract total_unstaked_and_waiting decremented...
                 self.total_unstaked_and_waiting.saturating_sub(amount); // ... because is no longer waiting
             self.contract_account_balance += amount; // the amount is now in the contract balance
-            //log event
-            event!(
-                r#"{{"event":"retrieve","sp":"{}","amount":"{}"}}"#,
-                sp_account_id,
-                amount
-            );
+            events::Retrieve {
+                sp: &sp_account_id,
+                amount: amount.into(),
+            }
+            .emit();
             // the amount retrieved should be considered "retrieved_for_unstake_claims" until the user calls withdraw_unstaked
             self.consider_retrieved_for_unstake_claims(amount);
 
@@ -22,7 +22,10 @@ impl MetaPool {
         registration_only: Option<bool>,
     ) -> StorageBalance {
         // get account_id
-        let account_id:String = if account_id.is_some() {account_id.unwrap().into()} else {env::predecessor_account_id()};
+        let account_id: String = match account_id {
+            Some(account_id) => account_id.into(),
+            None => env::predecessor_account_id(),
+        };
         let opt_account = self.accounts.get(&account_id);
         // if account already exists, no more yoctos required
         let required = if opt_account.is_some() {
@@ -55,6 +58,7 @@ impl MetaPool {
     #[payable]
     pub fn storage_unregister(&mut self, force: Option<bool>) -> bool {
         assert_one_yocto();
+        self.assert_accounts_not_migrating();
         if let Some(account) = self.accounts.get(&env::predecessor_account_id()) {
             // account exists
             if !account.can_be_closed() {
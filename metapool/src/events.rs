@@ -12,10 +12,17 @@
 //! These events can be logged by calling `.emit()` on them if a single event, or calling
 //! [`FtMint::emit_many`], [`FtTransfer::emit_many`],
 //! or [`FtBurn::emit_many`] respectively.
+//!
+//! stNEAR is also the "stnear" NEP-245 token (see multi_token.rs), so every FT event
+//! logs its NEP-245 twin (mt_mint, mt_transfer, mt_burn) too.
+//!
+//! Meta Pool specific events use the `metapool` standard, see [`MetapoolEventKind`].
 
-use near_sdk::json_types::U128;
+use near_sdk::json_types::{Base58CryptoHash, U128, U64};
 use near_sdk::serde::Serialize;
 
+use crate::types::{AdminActionClass, Role, MT_TOKEN_STNEAR};
+
 use near_sdk::env;
 use near_sdk::serde_json;
 use near_sdk::AccountId;
@@ -27,6 +34,8 @@ use near_sdk::AccountId;
 #[serde(rename_all = "snake_case")]
 pub(crate) enum NearEvent<'a> {
     Nep141(Nep141Event<'a>),
+    Nep245(Nep245Event<'a>),
+    Metapool(MetapoolEvent<'a>),
 }
 
 impl<'a> NearEvent<'a> {
@@ -68,7 +77,17 @@ impl FtMint<'_> {
     /// Emits an FT mint event, through [`env::log_str`](near_sdk::env::log_str),
     /// where each [`FtMint`] represents the data of each mint.
     pub fn emit_many(data: &[FtMint<'_>]) {
-        new_141_v1(Nep141EventKind::FtMint(data)).emit()
+        new_141_v1(Nep141EventKind::FtMint(data)).emit();
+        let twins: Vec<MtMint> = data
+            .iter()
+            .map(|x| MtMint {
+                owner_id: x.owner_id,
+                token_ids: vec![MT_TOKEN_STNEAR],
+                amounts: vec![x.amount],
+                memo: x.memo,
+            })
+            .collect();
+        new_245_v1(Nep245EventKind::MtMint(&twins)).emit()
     }
 }
 
@@ -95,7 +114,18 @@ impl FtTransfer<'_> {
     /// Emits an FT transfer event, through [`env::log_str`](near_sdk::env::log_str),
     /// where each [`FtTransfer`] represents the data of each transfer.
     pub fn emit_many(data: &[FtTransfer<'_>]) {
-        new_141_v1(Nep141EventKind::FtTransfer(data)).emit()
+        new_141_v1(Nep141EventKind::FtTransfer(data)).emit();
+        let twins: Vec<MtTransfer> = data
+            .iter()
+            .map(|x| MtTransfer {
+                old_owner_id: x.old_owner_id,
+                new_owner_id: x.new_owner_id,
+                token_ids: vec![MT_TOKEN_STNEAR],
+                amounts: vec![x.amount],
+                memo: x.memo,
+            })
+            .collect();
+        new_245_v1(Nep245EventKind::MtTransfer(&twins)).emit()
     }
 }
 
@@ -120,7 +150,17 @@ impl FtBurn<'_> {
     /// Emits an FT burn event, through [`env::log_str`](near_sdk::env::log_str),
     /// where each [`FtBurn`] represents the data of each burn.
     pub fn emit_many<'a>(data: &'a [FtBurn<'a>]) {
-        new_141_v1(Nep141EventKind::FtBurn(data)).emit()
+        new_141_v1(Nep141EventKind::FtBurn(data)).emit();
+        let twins: Vec<MtBurn> = data
+            .iter()
+            .map(|x| MtBurn {
+                owner_id: x.owner_id,
+                token_ids: vec![MT_TOKEN_STNEAR],
+                amounts: vec![x.amount],
+                memo: x.memo,
+            })
+            .collect();
+        new_245_v1(Nep245EventKind::MtBurn(&twins)).emit()
     }
 }
 
@@ -150,3 +190,472 @@ fn new_141<'a>(version: &'static str, event_kind: Nep141EventKind<'a>) -> NearEv
 fn new_141_v1(event_kind: Nep141EventKind) -> NearEvent {
     new_141("1.0.0", event_kind)
 }
+
+//-----------------------------------------------------------------------------------
+// NEP-245 (Multi Token) events, see multi_token.rs
+// <https://github.com/near/NEPs/blob/master/neps/nep-0245.md>
+//-----------------------------------------------------------------------------------
+
+/// Data to log for an MT mint event. To log this event, call [`.emit()`](MtMint::emit).
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct MtMint<'a> {
+    pub owner_id: &'a AccountId,
+    pub token_ids: Vec<&'a str>,
+    pub amounts: Vec<U128>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memo: Option<&'a str>,
+}
+
+impl MtMint<'_> {
+    /// Logs the event to the host.
+    pub fn emit(self) {
+        new_245_v1(Nep245EventKind::MtMint(&[self])).emit()
+    }
+}
+
+/// Data to log for an MT transfer event. To log this event, call [`.emit()`](MtTransfer::emit).
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct MtTransfer<'a> {
+    pub old_owner_id: &'a AccountId,
+    pub new_owner_id: &'a AccountId,
+    pub token_ids: Vec<&'a str>,
+    pub amounts: Vec<U128>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memo: Option<&'a str>,
+}
+
+impl MtTransfer<'_> {
+    /// Logs the event to the host.
+    pub fn emit(self) {
+        new_245_v1(Nep245EventKind::MtTransfer(&[self])).emit()
+    }
+}
+
+/// Data to log for an MT burn event. To log this event, call [`.emit()`](MtBurn::emit).
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct MtBurn<'a> {
+    pub owner_id: &'a AccountId,
+    pub token_ids: Vec<&'a str>,
+    pub amounts: Vec<U128>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memo: Option<&'a str>,
+}
+
+impl MtBurn<'_> {
+    /// Logs the event to the host.
+    pub fn emit(self) {
+        new_245_v1(Nep245EventKind::MtBurn(&[self])).emit()
+    }
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub(crate) struct Nep245Event<'a> {
+    version: &'static str,
+    #[serde(flatten)]
+    event_kind: Nep245EventKind<'a>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(tag = "event", content = "data")]
+#[serde(rename_all = "snake_case")]
+#[allow(clippy::enum_variant_names)]
+enum Nep245EventKind<'a> {
+    MtMint(&'a [MtMint<'a>]),
+    MtTransfer(&'a [MtTransfer<'a>]),
+    MtBurn(&'a [MtBurn<'a>]),
+}
+
+fn new_245_v1(event_kind: Nep245EventKind) -> NearEvent {
+    NearEvent::Nep245(Nep245Event {
+        version: "1.0.0",
+        event_kind,
+    })
+}
+
+//-----------------------------------------------------------------------------------
+// Meta Pool events, standard "metapool" (nep-297)
+// Every state change of the contract logs one of these events.
+// Log them by calling `.emit()` on the event data.
+//-----------------------------------------------------------------------------------
+
+macro_rules! metapool_event {
+    ($name:ident<'a>, $variant:ident) => {
+        impl<'a> $name<'a> {
+            /// Logs the event to the host
+            pub fn emit(self) {
+                new_metapool_v1(MetapoolEventKind::$variant(&[self])).emit()
+            }
+        }
+    };
+    ($name:ident, $variant:ident) => {
+        impl $name {
+            /// Logs the event to the host
+            pub fn emit(self) {
+                new_metapool_v1(MetapoolEventKind::$variant(&[self])).emit()
+            }
+        }
+    };
+}
+
+/// NEAR deposited into an account's available balance
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Deposit<'a> {
+    pub account_id: &'a AccountId,
+    pub amount: U128,
+}
+metapool_event!(Deposit<'a>, Deposit);
+
+/// available NEAR staked, stNEAR minted for the account
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Stake<'a> {
+    pub account_id: &'a AccountId,
+    pub amount: U128,
+    pub stnear: U128,
+}
+metapool_event!(Stake<'a>, Stake);
+
+/// delayed unstake, stNEAR burned, NEAR claimable after unlock_epoch
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct DelayedUnstake<'a> {
+    pub account_id: &'a AccountId,
+    pub amount: U128,
+    pub stnear: U128,
+    pub unlock_epoch: U64,
+}
+metapool_event!(DelayedUnstake<'a>, DelayedUnstake);
+
+/// unstaked NEAR claimed, moved to the account's available balance
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct UnstakeClaim<'a> {
+    pub account_id: &'a AccountId,
+    pub amount: U128,
+}
+metapool_event!(UnstakeClaim<'a>, UnstakeClaim);
+
+/// NEAR transferred from the contract to the account
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Withdraw<'a> {
+    pub account_id: &'a AccountId,
+    pub amount: U128,
+}
+metapool_event!(Withdraw<'a>, Withdraw);
+
+/// stNEAR swapped for NEAR in the liquidity pool
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct LiquidUnstake<'a> {
+    pub account_id: &'a AccountId,
+    pub stnear: U128,
+    pub near: U128,
+    /// fee in stNEAR
+    pub fee: U128,
+}
+metapool_event!(LiquidUnstake<'a>, LiquidUnstake);
+
+/// stNEAR bought from the NEAR/stNEAR liquidity pool
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct BuyStNear<'a> {
+    pub account_id: &'a AccountId,
+    pub near: U128,
+    pub stnear: U128,
+}
+metapool_event!(BuyStNear<'a>, BuyStNear);
+
+/// NEAR added to the NEAR/stNEAR liquidity pool
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct AddLiquidity<'a> {
+    pub account_id: &'a AccountId,
+    pub amount: U128,
+    pub shares: U128,
+}
+metapool_event!(AddLiquidity<'a>, AddLiquidity);
+
+/// liquidity removed from the NEAR/stNEAR liquidity pool
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct RemoveLiquidity<'a> {
+    pub account_id: &'a AccountId,
+    pub near: U128,
+    pub stnear: U128,
+    pub shares: U128,
+}
+metapool_event!(RemoveLiquidity<'a>, RemoveLiquidity);
+
+/// liquidity pool stNEAR burned against stake orders (0-fee swap)
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct NslpClearing {
+    pub stnear: U128,
+    pub near: U128,
+}
+metapool_event!(NslpClearing, NslpClearing);
+
+/// stake & unstake orders cancelled against each other at end of epoch
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct OrdersClearing {
+    pub keep: U128,
+}
+metapool_event!(OrdersClearing, OrdersClearing);
+
+/// extra NEAR retrieved by a rebalance, sent to stake orders
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Rebalance {
+    pub extra: U128,
+    pub retrieved_for_unstake_claims: U128,
+    pub total_unstake_claims: U128,
+    pub unstaked_for_rebalance: U128,
+}
+metapool_event!(Rebalance, Rebalance);
+
+/// NEAR staked in a staking pool by distribute_staking
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct DistributeStake<'a> {
+    pub sp: &'a AccountId,
+    pub amount: U128,
+    /// false if the NEAR were already unstaked in the pool (no transfer)
+    pub deposited: bool,
+}
+metapool_event!(DistributeStake<'a>, DistributeStake);
+
+/// NEAR unstaked from a staking pool by distribute_unstaking or a rebalance
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct DistributeUnstake<'a> {
+    pub sp: &'a AccountId,
+    pub amount_from_unstake_orders: U128,
+    pub amount_from_rebalance: U128,
+}
+metapool_event!(DistributeUnstake<'a>, DistributeUnstake);
+
+/// unstaked NEAR retrieved from a staking pool
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Retrieve<'a> {
+    pub sp: &'a AccountId,
+    pub amount: U128,
+}
+metapool_event!(Retrieve<'a>, Retrieve);
+
+/// staking rewards booked from a staking pool
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Rewards<'a> {
+    pub sp: &'a AccountId,
+    pub rewards: U128,
+    pub new_total_balance: U128,
+}
+metapool_event!(Rewards<'a>, Rewards);
+
+/// stNEAR minted as a fee
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FeeMint<'a> {
+    pub account_id: &'a AccountId,
+    pub stnear: U128,
+}
+metapool_event!(FeeMint<'a>, FeeMint);
+
+/// a contract parameter or configuration changed by an admin method
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct AdminChange<'a> {
+    /// method called
+    pub method: &'a str,
+    pub by: &'a AccountId,
+    /// account affected, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub account_id: Option<&'a AccountId>,
+    /// new value, if simple
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+}
+metapool_event!(AdminChange<'a>, AdminChange);
+
+/// operations paused or unpaused
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Pause<'a> {
+    pub paused: bool,
+    pub operations: u32,
+    pub paused_operations: u32,
+    pub by: &'a AccountId,
+}
+metapool_event!(Pause<'a>, Pause);
+
+/// a role granted, revoked or renounced
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct RoleChange<'a> {
+    /// grant, revoke or renounce
+    pub action: &'a str,
+    pub role: Role,
+    pub account_id: &'a AccountId,
+    pub by: &'a AccountId,
+}
+metapool_event!(RoleChange<'a>, RoleChange);
+
+/// a timelocked admin action queued, executed or cancelled
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TimelockedAction<'a> {
+    /// queue, execute or cancel
+    pub action: &'a str,
+    pub id: u32,
+    pub class: AdminActionClass,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub executable_at: Option<U64>,
+    pub by: &'a AccountId,
+}
+metapool_event!(TimelockedAction<'a>, TimelockedAction);
+
+/// timelock delay of an admin action class changed
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TimelockDelay {
+    pub class: AdminActionClass,
+    pub seconds: u32,
+}
+metapool_event!(TimelockDelay, TimelockDelay);
+
+/// a key account (owner, operator, treasury) change proposed or accepted
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct AccountTransfer<'a> {
+    /// propose or accept
+    pub action: &'a str,
+    pub key: &'a str,
+    pub account_id: &'a AccountId,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<U64>,
+}
+metapool_event!(AccountTransfer<'a>, AccountTransfer);
+
+/// contract code staged, cancelled or deployed
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Upgrade<'a> {
+    /// stage, cancel or deploy
+    pub action: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code_hash: Option<Base58CryptoHash>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deployable_at: Option<U64>,
+    pub by: &'a AccountId,
+}
+metapool_event!(Upgrade<'a>, Upgrade);
+
+/// state migrated to a new version
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Migrate {
+    pub state_version: u16,
+    pub accounts_version: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stored_bytes: Option<U64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new_bytes: Option<U64>,
+}
+metapool_event!(Migrate, Migrate);
+
+/// a batch of accounts rewritten in the current layout
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct MigrateAccounts {
+    pub from_index: U64,
+    pub to_index: U64,
+    pub migrated: u32,
+    pub storage_reclaimed: U64,
+    pub accounts_version: u16,
+}
+metapool_event!(MigrateAccounts, MigrateAccounts);
+
+/// a proof of reserves completed, see verify_reserves
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Reserves<'a> {
+    pub total_reserves: U128,
+    pub total_liabilities: U128,
+    pub ok: bool,
+    pub by: &'a AccountId,
+}
+metapool_event!(Reserves<'a>, Reserves);
+
+/// extra balance accumulated (gas rebate) routed, see gas_rebate_split
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct GasRebate {
+    pub amount: U128,
+    pub operator: U128,
+    pub treasury: U128,
+    pub staking: U128,
+    pub total_routed: U128,
+}
+metapool_event!(GasRebate, GasRebate);
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub(crate) struct MetapoolEvent<'a> {
+    version: &'static str,
+    #[serde(flatten)]
+    event_kind: MetapoolEventKind<'a>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(tag = "event", content = "data")]
+#[serde(rename_all = "snake_case")]
+enum MetapoolEventKind<'a> {
+    Deposit(&'a [Deposit<'a>]),
+    Stake(&'a [Stake<'a>]),
+    DelayedUnstake(&'a [DelayedUnstake<'a>]),
+    UnstakeClaim(&'a [UnstakeClaim<'a>]),
+    Withdraw(&'a [Withdraw<'a>]),
+    LiquidUnstake(&'a [LiquidUnstake<'a>]),
+    BuyStNear(&'a [BuyStNear<'a>]),
+    AddLiquidity(&'a [AddLiquidity<'a>]),
+    RemoveLiquidity(&'a [RemoveLiquidity<'a>]),
+    NslpClearing(&'a [NslpClearing]),
+    OrdersClearing(&'a [OrdersClearing]),
+    Rebalance(&'a [Rebalance]),
+    DistributeStake(&'a [DistributeStake<'a>]),
+    DistributeUnstake(&'a [DistributeUnstake<'a>]),
+    Retrieve(&'a [Retrieve<'a>]),
+    Rewards(&'a [Rewards<'a>]),
+    FeeMint(&'a [FeeMint<'a>]),
+    AdminChange(&'a [AdminChange<'a>]),
+    Pause(&'a [Pause<'a>]),
+    RoleChange(&'a [RoleChange<'a>]),
+    TimelockedAction(&'a [TimelockedAction<'a>]),
+    TimelockDelay(&'a [TimelockDelay]),
+    AccountTransfer(&'a [AccountTransfer<'a>]),
+    Upgrade(&'a [Upgrade<'a>]),
+    Migrate(&'a [Migrate]),
+    MigrateAccounts(&'a [MigrateAccounts]),
+    Reserves(&'a [Reserves<'a>]),
+    GasRebate(&'a [GasRebate]),
+}
+
+fn new_metapool_v1(event_kind: MetapoolEventKind) -> NearEvent {
+    NearEvent::Metapool(MetapoolEvent {
+        version: "1.0.0",
+        event_kind,
+    })
+}
@@ -51,9 +51,7 @@ fn ft_metadata_default() -> FungibleTokenMetadata {
     }
 }
 fn ft_metadata_init_lazy_container() -> LazyOption<FungibleTokenMetadata> {
-    let metadata: LazyOption<FungibleTokenMetadata>;
-    metadata = LazyOption::new(b"ftmd".to_vec(), None);
-    return metadata;
+    return LazyOption::new(b"ftmd".to_vec(), None);
 }
 
 #[near_bindgen]
@@ -71,6 +69,7 @@ impl FungibleTokenCore for MetaPool {
         #[allow(unused)] memo: Option<String>,
     ) {
         assert_one_yocto();
+        self.assert_operation_not_paused(PAUSE_ST_NEAR_TRANSFER);
         //log!("env::storage_byte_cost {}",env::storage_byte_cost());
         //log!("env::storage_usage {}",env::storage_usage());
         self.internal_st_near_transfer(
@@ -91,6 +90,7 @@ impl FungibleTokenCore for MetaPool {
         msg: String,
     ) -> PromiseOrValue<U128> {
         assert_one_yocto();
+        self.assert_operation_not_paused(PAUSE_ST_NEAR_TRANSFER);
         assert!(
             env::prepaid_gas() > GAS_FOR_FT_TRANSFER_CALL + GAS_FOR_RESOLVE_TRANSFER + FIVE_TGAS,
             "gas required {}",
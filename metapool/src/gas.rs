@@ -85,9 +85,9 @@ pub mod owner_callbacks {
     /// Requires BASE for local updates.
     pub const ON_STAKING_POOL_UNSTAKE: u64 = super::BASE_GAS;
 
-    /// Gas attached to the inner callback for processing result of the unstake all call to the
-    /// staking pool.
-    /// Requires BASE for local updates.
+    // Gas attached to the inner callback for processing result of the unstake all call to the
+    // staking pool.
+    // Requires BASE for local updates.
     //pub const ON_STAKING_POOL_UNSTAKE_ALL: u64 = super::BASE_GAS;
 
     /// Gas attached to the inner callback for processing result of the checking result for
@@ -98,6 +98,9 @@ pub mod owner_callbacks {
     /// Gas attached to the inner callback for processing result of the call to get the current total balance from the staking pool.
     pub const ON_GET_SP_TOTAL_BALANCE: u64 = super::BASE_GAS * 5; //because distributes realized rewards to author and owner
 
+    /// Gas attached to the inner callback for verify_reserves, stores the balances reported by a batch of staking pools.
+    pub const ON_VERIFY_RESERVES: u64 = super::BASE_GAS;
+
     /// Gas attached to the inner callback for sync_unstaked_balance to get precise unstaked balance from the staking pool.
     pub const ON_GET_SP_UNSTAKED_BALANCE: u64 = super::BASE_GAS; // just update unstaked amount (yocto differences)
 
@@ -19,20 +19,167 @@ pub struct GSPRUResult {
 /****************************/
 impl MetaPool {
     /// Asserts that the method was called by the owner.
+    /// Only owner_account_id, Role::Owner can not be granted
     pub fn assert_owner_calling(&self) {
-        assert_eq!(
-            &env::predecessor_account_id(),
-            &self.owner_account_id,
+        assert!(
+            env::predecessor_account_id() == self.owner_account_id,
             "Can only be called by the owner"
         )
     }
     pub fn assert_operator_or_owner(&self) {
         assert!(
-            &env::predecessor_account_id() == &self.owner_account_id
-                || &env::predecessor_account_id() == &self.operator_account_id,
+            self.has_role(&env::predecessor_account_id(), Role::Operator),
             "Can only be called by the operator or the owner"
         );
     }
+    /// Asserts the caller holds `role` (the owner holds every role)
+    pub fn assert_role(&self, role: Role) {
+        assert!(
+            self.has_role(&env::predecessor_account_id(), role),
+            "Can only be called by the owner or an account with role {:?}",
+            role
+        );
+    }
+
+    /// owner_account_id implicitly holds every role (and is the only holder of Role::Owner),
+    /// operator_account_id implicitly holds Role::Operator,
+    /// other accounts hold the roles granted in self.roles
+    pub fn has_role(&self, account_id: &AccountId, role: Role) -> bool {
+        if account_id == &self.owner_account_id {
+            return true;
+        }
+        if role == Role::Operator && account_id == &self.operator_account_id {
+            return true;
+        }
+        match self.roles.get(account_id) {
+            Some(granted) => role != Role::Owner && granted.contains(&role),
+            None => false,
+        }
+    }
+
+    /// Asserts the caller is one of the authorized lockup proxy contracts
+    pub fn assert_lockup_contract_calling(&self) {
+        assert!(
+            self.lockup_contracts.contains(&env::predecessor_account_id()),
+            "the function can only be operated by an authorized lockup contract"
+        );
+    }
+
+    /// verify if it is a lockup account
+    pub fn is_lockup_account(&self, account_id: &str) -> bool {
+        self.lockup_account_suffixes
+            .iter()
+            .any(|suffix| account_id.ends_with(suffix))
+    }
+
+    /// slippage protection, the tx must be included before the deadline block height
+    pub fn assert_deadline_not_passed(&self, deadline_block_height: Option<U64String>) {
+        if let Some(deadline) = deadline_block_height {
+            assert!(
+                env::block_index() <= deadline.0,
+                "deadline block height {} passed, current {}",
+                deadline.0,
+                env::block_index()
+            );
+        }
+    }
+
+    /// assert it is not a lockup account
+    pub fn assert_not_lockup_account_calling(&self) {
+        assert!(
+            !self.is_lockup_account(&env::predecessor_account_id()),
+            "a lockup account can not be used here"
+        );
+    }
+
+    /// Asserts none of the operations in the bitmask are paused
+    pub fn assert_operation_not_paused(&self, operation: u32) {
+        assert!(
+            self.paused_operations & operation == 0,
+            "operation paused ({}). Try again later",
+            self.paused_operations & operation
+        );
+    }
+
+    /// upgrade safety checks, checked before deploying new code and before & after `migrate`.
+    /// The contract must be at rest: not busy, no staking pool busy and totals matching the sum of the staking pools.
+    /// The sums are compared with is_close (+/- ONE_MILLI_NEAR), tolerating the yocto
+    /// rounding drift accumulated by the staking-pool share math
+    pub(crate) fn assert_upgrade_invariants(&self, stage: &str) {
+        assert!(!self.contract_busy, "{}: contract is busy", stage);
+        let mut sum_staked: u128 = 0;
+        let mut sum_unstaked: u128 = 0;
+        for (sp_inx, sp) in self.staking_pools.iter().enumerate() {
+            assert!(!sp.busy_lock, "{}: sp {} is busy", stage, sp_inx);
+            sum_staked += sp.staked;
+            sum_unstaked += sp.unstaked;
+        }
+        assert!(
+            is_close(sum_staked, self.total_actually_staked),
+            "{}: total_actually_staked {} != sum(sp.staked) {}",
+            stage,
+            self.total_actually_staked,
+            sum_staked
+        );
+        assert!(
+            is_close(sum_unstaked, self.total_unstaked_and_waiting),
+            "{}: total_unstaked_and_waiting {} != sum(sp.unstaked) {}",
+            stage,
+            self.total_unstaked_and_waiting,
+            sum_unstaked
+        );
+    }
+
+    /// first step of a key account change (owner, operator, treasury)
+    pub(crate) fn internal_propose_account(
+        &self,
+        key: &str,
+        account_id: AccountId,
+    ) -> PendingAccountTransfer {
+        let expires_at = env::block_timestamp() + ACCOUNT_TRANSFER_EXPIRY_NANOSECONDS;
+        events::AccountTransfer {
+            action: "propose",
+            key,
+            account_id: &account_id,
+            expires_at: Some(expires_at.into()),
+        }
+        .emit();
+        PendingAccountTransfer {
+            account_id,
+            proposed_by: env::predecessor_account_id(),
+            expires_at: expires_at.into(),
+        }
+    }
+
+    /// second step, the proposed account must be calling before expiration
+    pub(crate) fn internal_accept_account(
+        &self,
+        key: &str,
+        pending: &Option<PendingAccountTransfer>,
+    ) -> AccountId {
+        let pending = match pending {
+            Some(pending) => pending,
+            None => panic!("no pending {} transfer", key),
+        };
+        assert!(
+            env::predecessor_account_id() == pending.account_id,
+            "Can only be accepted by the proposed {} account",
+            key
+        );
+        assert!(
+            env::block_timestamp() <= pending.expires_at.0,
+            "the {} transfer proposal has expired",
+            key
+        );
+        events::AccountTransfer {
+            action: "accept",
+            key,
+            account_id: &pending.account_id,
+            expires_at: None,
+        }
+        .emit();
+        pending.account_id.clone()
+    }
 
     pub fn assert_not_busy(&self) {
         assert!(!self.contract_busy, "Contract is busy. Try again later");
@@ -59,7 +206,7 @@ impl MetaPool {
     // adds env::attached_deposit() to account.available
     // if it is a new account, takes STORAGE_COST_YOCTOS as storage_deposit
     pub(crate) fn internal_deposit_attached_near_into(&mut self, account_id: &String) -> u128 {
-        let opt_account = self.accounts.get(&account_id);
+        let opt_account = self.accounts.get(account_id);
         let amount = if opt_account.is_none() {
             // account does not exists
             // take some yoctos as storage deposit - the user can recover that amount when closing the account
@@ -82,7 +229,7 @@ impl MetaPool {
         self.total_available += amount;
         self.contract_account_balance += amount;
 
-        self.internal_update_account(&account_id, &account);
+        self.internal_update_account(account_id, &account);
 
         log!(
             "{} deposited into @{}'s account. New available balance is {}",
@@ -90,6 +237,18 @@ impl MetaPool {
             account_id,
             account.available
         );
+        events::Deposit {
+            account_id,
+            amount: amount.into(),
+        }
+        .emit();
+        events::MtMint {
+            owner_id: account_id,
+            token_ids: vec![MT_TOKEN_NEAR],
+            amounts: vec![amount.into()],
+            memo: Some("deposit"),
+        }
+        .emit();
         amount
     }
 
@@ -100,10 +259,10 @@ impl MetaPool {
         account_id: &String,
         requested_amount: u128,
     ) -> Promise {
-        let mut account = self.internal_get_account(&account_id);
+        let mut account = self.internal_get_account(account_id);
 
         //MIMIC staking-pool, move 1st form unstaked->available, it must be free to withdraw
-        account.in_memory_try_finish_unstaking(&account_id, requested_amount, self);
+        account.in_memory_try_finish_unstaking(account_id, requested_amount, self);
 
         // NOTE: While ability to withdraw close to all available helps, it prevents lockup contracts from using this in a replacement to a staking pool,
         // because the lockup contracts relies on exact precise amount being withdrawn.
@@ -115,13 +274,18 @@ impl MetaPool {
         //     "The min balance for an open account is {} NEAR. You need to close the account to remove all funds",
         //     self.min_account_balance/NEAR);
 
-        self.internal_update_account(&account_id, &account);
+        self.internal_update_account(account_id, &account);
         //transfer to user native near account
         self.native_transfer(account_id, amount)
     }
     pub(crate) fn native_transfer(&mut self, account_id: &String, amount: u128) -> Promise {
         //transfer to user native near account
         self.contract_account_balance -= amount;
+        events::Withdraw {
+            account_id,
+            amount: amount.into(),
+        }
+        .emit();
         Promise::new(account_id.clone()).transfer(amount)
     }
 
@@ -129,6 +293,32 @@ impl MetaPool {
     /// takes from account.available and mints stNEAR for account_id
     /// actual stake in a staking-pool is made by the meta-pool-heartbeat before the end of the epoch
     /// account_id must be registered
+    /// min amount accepted by internal_stake_from_account
+    pub(crate) fn internal_min_stake_from_account(&self) -> u128 {
+        self.min_deposit_amount.saturating_sub(STORAGE_COST_YOCTOS)
+    }
+
+    /// NEAR->stNEAR from the NSLP stNEAR, at nslp_buy_discount_basis_points below its value
+    /// returns (NEAR paid to the NSLP, stNEAR bought), limited by the NSLP stNEAR
+    pub(crate) fn internal_quote_buy_stnear(&self, near_amount: u128) -> (u128, u128) {
+        let nslp_account = self.internal_get_nslp_account();
+        // discounted price of all the NSLP stNEAR
+        let nslp_st_near_price = apply_pct(
+            10_000 - self.nslp_buy_discount_basis_points,
+            self.amount_from_stake_shares(nslp_account.stake_shares),
+        );
+        if nslp_st_near_price == 0 {
+            (0, 0)
+        } else if near_amount >= nslp_st_near_price {
+            (nslp_st_near_price, nslp_account.stake_shares)
+        } else {
+            (
+                near_amount,
+                proportional(nslp_account.stake_shares, near_amount, nslp_st_near_price),
+            )
+        }
+    }
+
     pub(crate) fn internal_stake_from_account(
         &mut self,
         account_id: &String,
@@ -137,16 +327,16 @@ impl MetaPool {
         self.assert_not_busy();
 
         assert!(
-            near_amount >= self.min_deposit_amount.saturating_sub(STORAGE_COST_YOCTOS),
+            near_amount >= self.internal_min_stake_from_account(),
             "min deposit amount is {}",
             self.min_deposit_amount
         );
 
-        let mut acc = self.internal_get_account(&account_id);
+        let mut acc = self.internal_get_account(account_id);
 
         // take from the account "available" balance
         // also subs self.total_available
-        let amount = acc.take_from_available(&account_id, near_amount, self);
+        let amount = acc.take_from_available(account_id, near_amount, self);
 
         // Calculate the number of st_near (stake shares) that the account will receive for staking the given amount.
         let num_shares = self.stake_shares_from_amount(amount);
@@ -160,14 +350,20 @@ impl MetaPool {
         self.epoch_stake_orders += amount;
 
         //--SAVE ACCOUNT--
-        self.internal_update_account(&account_id, &acc);
+        self.internal_update_account(account_id, &acc);
 
-        //log event
-        event!(
-            r#"{{"event":"STAKE","account":"{}","amount":"{}"}}"#,
+        events::Stake {
             account_id,
-            amount
-        );
+            amount: amount.into(),
+            stnear: num_shares.into(),
+        }
+        .emit();
+        events::FtMint {
+            owner_id: account_id,
+            amount: num_shares.into(),
+            memo: None,
+        }
+        .emit();
         num_shares
     }
 
@@ -176,7 +372,7 @@ impl MetaPool {
     pub(crate) fn internal_unstake(&mut self, account_id: &String, amount_requested: u128) {
         self.assert_not_busy();
 
-        let mut acc = self.internal_get_account(&account_id);
+        let mut acc = self.internal_get_account(account_id);
 
         // compute how much shares it will be
         let shares_from_requested = self.stake_shares_from_amount(amount_requested);
@@ -192,14 +388,6 @@ impl MetaPool {
             shares_from_requested
         };
         self.internal_unstake_shares(account_id, &mut acc, stake_shares_to_burn);
-
-        events::FtBurn {
-            owner_id: &account_id,
-            amount: stake_shares_to_burn.into(),
-            memo: None
-        }
-        .emit();
-
     }
 
     pub(crate) fn internal_unstake_shares(
@@ -224,14 +412,21 @@ impl MetaPool {
         self.total_for_staking -= amount_to_unstake;
 
         //--SAVE ACCOUNT--
-        self.internal_update_account(&account_id, acc);
+        self.internal_update_account(account_id, acc);
 
-        event!(
-            r#"{{"event":"D-UNSTK","account_id":"{}","amount":"{}","shares":"{}"}}"#,
+        events::DelayedUnstake {
             account_id,
-            amount_to_unstake,
-            stake_shares_to_burn
-        );
+            amount: amount_to_unstake.into(),
+            stnear: stake_shares_to_burn.into(),
+            unlock_epoch: acc.unstaked_requested_unlock_epoch.into(),
+        }
+        .emit();
+        events::FtBurn {
+            owner_id: account_id,
+            amount: stake_shares_to_burn.into(),
+            memo: None,
+        }
+        .emit();
 
         log!(
             "@{} unstaked {}. Has now {} unstaked and {} stNEAR. Epoch:{}",
@@ -248,14 +443,16 @@ impl MetaPool {
     //--------------------------------------------------
     /// adds liquidity from deposited amount
     /// account mus be registered previously
+    /// panics if the account would receive less than min_shares_out nslp shares
     pub(crate) fn internal_nslp_add_liquidity(
         &mut self,
         account_id: &String,
         amount_requested: u128,
+        min_shares_out: u128,
     ) -> u16 {
         self.assert_not_busy();
 
-        let mut acc = self.internal_get_account(&account_id);
+        let mut acc = self.internal_get_account(account_id);
 
         //take from the account "available" balance
         let amount = acc.take_from_available(account_id, amount_requested, self);
@@ -266,11 +463,17 @@ impl MetaPool {
         // Calculate the number of "nslp" shares the account will receive for adding the given amount of near liquidity
         let num_shares = self.nslp_shares_from_amount(amount, &nslp_account);
         assert!(num_shares > 0);
-
-        //register added liquidity to compute rewards correctly
-        acc.lp_meter.stake(amount);
+        assert!(
+            num_shares >= min_shares_out,
+            "Price changed, you will get only {} nslp shares, min_shares_out {}",
+            num_shares,
+            min_shares_out
+        );
 
         //update user account
+        if acc.is_nslp_cost_basis_tracked() {
+            acc.nslp_cost_basis += amount;
+        }
         acc.nslp_shares += num_shares;
         //update NSLP account & main
         nslp_account.available += amount;
@@ -281,24 +484,206 @@ impl MetaPool {
         let result_bp = proportional(10_000, acc.nslp_shares, nslp_account.nslp_shares) as u16;
 
         //--SAVE ACCOUNTS
-        self.internal_update_account(&account_id, &acc);
+        self.internal_update_account(account_id, &acc);
         self.internal_save_nslp_account(&nslp_account);
 
-        event!(
-            r#"{{"event":"ADD.L","account_id":"{}","amount":"{}"}}"#,
+        events::AddLiquidity {
             account_id,
-            amount
-        );
+            amount: amount.into(),
+            shares: num_shares.into(),
+        }
+        .emit();
+        events::MtMint {
+            owner_id: account_id,
+            token_ids: vec![MT_TOKEN_NSLP],
+            amounts: vec![num_shares.into()],
+            memo: None,
+        }
+        .emit();
+        // the NEAR taken from the user's available goes to the NSLP's available
+        events::MtMint {
+            owner_id: &NSLP_INTERNAL_ACCOUNT.into(),
+            token_ids: vec![MT_TOKEN_NEAR],
+            amounts: vec![amount.into()],
+            memo: Some("nslp_add_liquidity"),
+        }
+        .emit();
 
         return result_bp;
     }
 
+    //--------------------------------------------------
+    /// swaps stNEAR->NEAR in the Liquidity Pool, sends the NEAR to account_id
+    /// used by liquid_unstake & liquid_unstake_for_lockup
+    /// returns nears transferred
+    pub(crate) fn internal_liquid_unstake(
+        &mut self,
+        account_id: &str,
+        st_near_to_burn: u128,
+        min_expected_near: u128,
+    ) -> LiquidUnstakeResult {
+        self.assert_not_busy();
+
+        let account_id = account_id.to_string();
+        let mut user_account = self.internal_get_account(&account_id);
+
+        let stnear_owned = user_account.stake_shares;
+
+        let st_near_to_sell:u128 =
+        // if the amount is close to user's total, remove user's total
+        // to: a) do not leave less than ONE_MILLI_NEAR in the account, b) Allow 10 yoctos of rounding, e.g. remove(100) removes 99.999993 without panicking
+        // Audit Note: Do not do this for .lockup accounts because the lockup contract relies on precise amounts
+        if !self.is_lockup_account(&account_id) && is_close(st_near_to_burn, stnear_owned) { // allow for rounding simplification
+            stnear_owned
+        }
+        else  {
+            st_near_to_burn
+        };
+
+        log!(
+            "st_near owned:{}, to_sell:{}",
+            user_account.stake_shares,
+            st_near_to_sell
+        );
+
+        assert!(
+            stnear_owned >= st_near_to_sell,
+            "Not enough stNEAR. You own {}",
+            stnear_owned
+        );
+
+        let mut nslp_account = self.internal_get_nslp_account();
+
+        //compute how many nears are the st_near valued at
+        let nears_out = self.amount_from_stake_shares(st_near_to_sell);
+        let swap_fee_basis_points =
+            self.internal_get_discount_basis_points(nslp_account.available, nears_out);
+        assert!(swap_fee_basis_points < 10000, "inconsistency d>1");
+        let fee = apply_pct(swap_fee_basis_points, nears_out);
+
+        let near_to_receive = nears_out - fee;
+        assert!(
+            near_to_receive >= min_expected_near,
+            "Price changed, your min amount {} is not satisfied {}. Try again",
+            min_expected_near,
+            near_to_receive
+        );
+        assert!(
+            nslp_account.available >= near_to_receive,
+            "Not enough liquidity in the liquidity pool"
+        );
+
+        //the NEAR for the user comes from the LP
+        nslp_account.available -= near_to_receive;
+        user_account.available += near_to_receive;
+
+        // compute how many shares the swap fee represent
+        let fee_in_st_near = self.stake_shares_from_amount(fee);
+
+        // The fee beneficiaries cuts in stnear-shares
+        // (by default treasury 25%, operator 3% & developers 2% of the fee)
+        let mut cuts: Vec<(AccountId, u128)> = Vec::with_capacity(self.fee_beneficiaries.len());
+        let mut total_cuts: u128 = 0;
+        for beneficiary in self.fee_beneficiaries.clone().iter() {
+            let beneficiary_id = self.fee_recipient_account_id(&beneficiary.recipient);
+            assert!(
+                account_id != beneficiary_id,
+                "can't use fee beneficiary account {}",
+                beneficiary_id
+            );
+            let st_near_cut = apply_pct(beneficiary.swap_cut_bp, fee_in_st_near);
+            if st_near_cut > 0 {
+                // load & save one by one, two beneficiaries could share an account
+                let mut beneficiary_account = self.accounts.get(&beneficiary_id).unwrap_or_default();
+                beneficiary_account.add_st_near(st_near_cut, self);
+                self.internal_update_account(&beneficiary_id, &beneficiary_account);
+                self.internal_record_fee(&beneficiary_id, 0, st_near_cut);
+                total_cuts += st_near_cut;
+            }
+            log!("{} st_near_cut:{}", beneficiary_id, st_near_cut);
+            cuts.push((beneficiary_id, st_near_cut));
+        }
+        log!("total_cuts:{} fee_in_st_near:{}", total_cuts, fee_in_st_near);
+
+        assert!(fee_in_st_near > total_cuts);
+
+        // The rest of the st_near sold goes into the liq-pool. Because it is a larger amount than NEARs removed, it will increase share value for all LP providers.
+        // Adding value to the pool via adding more stNEAR value than the NEAR removed
+        let st_near_to_liq_pool = st_near_to_sell - total_cuts;
+        log!("nslp_account.add_st_near {}", st_near_to_liq_pool);
+        // LP earnings: the NSLP receives more value than the NEAR it pays
+        self.nslp_total_fees_retained += self
+            .amount_from_stake_shares(st_near_to_liq_pool)
+            .saturating_sub(near_to_receive);
+        // major part of stNEAR sold goes to the NSLP
+        nslp_account.add_st_near(st_near_to_liq_pool, self);
+
+        //complete the transfer, remove stnear from the user (stnear was transferred to the LP & others)
+        user_account.sub_st_near(st_near_to_sell, self);
+
+        //Save nslp accounts
+        self.internal_save_nslp_account(&nslp_account);
+
+        //simplified user-flow
+        //direct transfer to user (instead of leaving it in-contract as "available")
+        let transfer_amount = user_account.take_from_available(&account_id, near_to_receive, self);
+        self.native_transfer(&account_id, transfer_amount);
+
+        //Save user account
+        self.internal_update_account(&account_id, &user_account);
+
+        // the stNEAR sold moves from the user to the NSLP and the fee beneficiaries
+        let nslp_account_id: AccountId = NSLP_INTERNAL_ACCOUNT.into();
+        let transfers: Vec<events::FtTransfer> = std::iter::once((&nslp_account_id, st_near_to_liq_pool))
+        .chain(cuts.iter().map(|(beneficiary_id, cut)| (beneficiary_id, *cut)))
+        .filter(|(_, amount)| *amount > 0)
+        .map(|(new_owner_id, amount)| events::FtTransfer {
+            old_owner_id: &account_id,
+            new_owner_id,
+            amount: amount.into(),
+            memo: Some("liquid_unstake"),
+        })
+        .collect();
+        if !transfers.is_empty() {
+            events::FtTransfer::emit_many(&transfers);
+        }
+        // the NEAR paid by the NSLP (then sent to the user, see take_from_available)
+        events::MtTransfer {
+            old_owner_id: &nslp_account_id,
+            new_owner_id: &account_id,
+            token_ids: vec![MT_TOKEN_NEAR],
+            amounts: vec![near_to_receive.into()],
+            memo: Some("liquid_unstake"),
+        }
+        .emit();
+
+        log!(
+            "@{} liquid-unstaked {} stNEAR, got {} NEAR",
+            &account_id,
+            st_near_to_sell,
+            transfer_amount
+        );
+        events::LiquidUnstake {
+            account_id: &account_id,
+            stnear: st_near_to_sell.into(),
+            near: transfer_amount.into(),
+            fee: fee_in_st_near.into(),
+        }
+        .emit();
+
+        return LiquidUnstakeResult {
+            near: transfer_amount.into(),
+            fee: fee_in_st_near.into(),
+            meta: 0.into(), // meta_to_seller.into(),
+        };
+    }
+
     //--------------------------------------------------
     /// computes unstaking delay on current situation
     pub fn internal_compute_current_unstaking_delay(&self, amount: u128) -> u64 {
         let mut total_staked: u128 = 0;
         let mut normal_wait_staked_available: u128 = 0;
-        for (_, sp) in self.staking_pools.iter().enumerate() {
+        for sp in self.staking_pools.iter() {
             //if the pool has no unstaking in process
             total_staked += sp.staked;
             if !sp.busy_lock && sp.staked > 0 && sp.wait_period_ended() {
@@ -321,14 +706,26 @@ impl MetaPool {
     // this fn MUST NOT PANIC (is called from a callback clearing busy flags)
     pub(crate) fn add_extra_minted_shares(&mut self, account_id: AccountId, num_shares: u128) {
         if num_shares > 0 {
-            // used only for operator & DEVELOPERS_ACCOUNT
+            // used only for the fee beneficiaries
             // use accounts.unwrap_or_default to not panic and also
             // create account if needed (for tests and first usage after init)
             let account = &mut self.accounts.get(&account_id).unwrap_or_default();
             account.stake_shares += num_shares;
-            self.internal_update_account(&account_id, &account);
+            self.internal_update_account(&account_id, account);
             // Increasing the total amount of stake shares (reduces price)
             self.total_stake_shares += num_shares;
+            self.internal_record_fee(&account_id, num_shares, 0);
+            events::FeeMint {
+                account_id: &account_id,
+                stnear: num_shares.into(),
+            }
+            .emit();
+            events::FtMint {
+                owner_id: &account_id,
+                amount: num_shares.into(),
+                memo: Some("fee"),
+            }
+            .emit();
         }
     }
 
@@ -391,12 +788,11 @@ impl MetaPool {
             };
 
             log!("NSLP clearing {} {}", st_near_to_sell, near_value);
-            //log event
-            event!(
-                r#"{{"event":"NSLP.clr","shares":"{}","amount":"{}"}}"#,
-                st_near_to_sell,
-                near_value
-            );
+            events::NslpClearing {
+                stnear: st_near_to_sell.into(),
+                near: near_value.into(),
+            }
+            .emit();
 
             // users made a deposit+mint, and now we need to convert that into a 0-fee swap NEAR<->stNEAR
             // we take NEAR from the contract, but let the users keep their minted stNEAR
@@ -412,6 +808,20 @@ impl MetaPool {
                                                         //save nslp account
             self.internal_save_nslp_account(&nslp_account);
 
+            events::FtBurn {
+                owner_id: &NSLP_INTERNAL_ACCOUNT.into(),
+                amount: st_near_to_sell.into(),
+                memo: Some("nslp_clearing"),
+            }
+            .emit();
+            events::MtMint {
+                owner_id: &NSLP_INTERNAL_ACCOUNT.into(),
+                token_ids: vec![MT_TOKEN_NEAR],
+                amounts: vec![near_value.into()],
+                memo: Some("nslp_clearing"),
+            }
+            .emit();
+
             return true;
         }
         return false;
@@ -434,17 +844,31 @@ impl MetaPool {
         }
         //amount after the swap
         let near_after = available_near - nears_requested;
-        if near_after >= self.nslp_liquidity_target {
+        return self.internal_nslp_discount_bp_at(near_after);
+    }
+
+    /// current NSLP liquidity target, absolute or relative to total_for_staking
+    pub(crate) fn internal_nslp_liquidity_target(&self) -> u128 {
+        match &self.nslp_relative_target {
+            Some(relative) => relative.target(self.total_for_staking),
+            None => self.nslp_liquidity_target,
+        }
+    }
+
+    /// fee curve: discount basis points when the NSLP is left with near_after liquidity
+    pub(crate) fn internal_nslp_discount_bp_at(&self, near_after: u128) -> u16 {
+        let target = self.internal_nslp_liquidity_target();
+        if near_after >= target {
             //still >= target
             return self.nslp_min_discount_basis_points;
         }
-
-        //linear curve from max to min on target
-        let range = self.nslp_max_discount_basis_points - self.nslp_min_discount_basis_points;
-        //here 0<near_after<self.nslp_liquidity_target, so 0<proportional_bp<range
-        let proportional_bp = proportional(range as u128, near_after, self.nslp_liquidity_target);
-
-        return self.nslp_max_discount_basis_points - proportional_bp as u16;
+        //here 0<=near_after<target
+        self.nslp_fee_curve.discount_bp(
+            near_after,
+            target,
+            self.nslp_min_discount_basis_points,
+            self.nslp_max_discount_basis_points,
+        )
     }
 
     /// NEAR/stNEAR SWAP functions
@@ -460,7 +884,7 @@ impl MetaPool {
             self.internal_get_discount_basis_points(available_near, nears_out);
         assert!(swap_fee_basis_points < 10000, "inconsistency d>1");
         let fee = apply_pct(swap_fee_basis_points, nears_out);
-        return (nears_out - fee).into(); //when stNEAR is sold user pays a swap fee (the user skips the waiting period)
+        return nears_out - fee; //when stNEAR is sold user pays a swap fee (the user skips the waiting period)
 
         // env::log(
         //     format!(
@@ -472,6 +896,8 @@ impl MetaPool {
     }
 
     /// Inner method to get the given account - IT MUST exists (has to be previously registered)
+    /// accounts stored in an older layout are upgraded when read,
+    /// and stored in the current layout by internal_update_account
     pub(crate) fn internal_get_account(&self, account_id: &String) -> Account {
         let opt_account = self.accounts.get(account_id);
         if opt_account.is_none() {
@@ -486,7 +912,7 @@ impl MetaPool {
 
     /// Inner method to save the given account for a given account ID.
     pub(crate) fn internal_update_account(&mut self, account_id: &String, account: &Account) {
-        self.accounts.insert(account_id, &account); //insert_or_update
+        self.accounts.insert(account_id, account); //insert_or_update
     }
 
     /// Inner method to get the given account or a new default value account.
@@ -496,7 +922,7 @@ impl MetaPool {
             .unwrap_or_default()
     }
     pub(crate) fn internal_save_nslp_account(&mut self, nslp_account: &Account) {
-        self.internal_update_account(&NSLP_INTERNAL_ACCOUNT.into(), &nslp_account);
+        self.internal_update_account(&NSLP_INTERNAL_ACCOUNT.into(), nslp_account);
     }
 
     /// finds a staking pool requiring some stake to get balanced
@@ -600,8 +1026,8 @@ impl MetaPool {
             "Sender and receiver should be different"
         );
         assert!(amount > 0, "The amount should be a positive number");
-        let mut sender_acc = self.internal_get_account(&sender_id);
-        let mut receiver_acc = self.internal_get_account(&receiver_id);
+        let mut sender_acc = self.internal_get_account(sender_id);
+        let mut receiver_acc = self.internal_get_account(receiver_id);
         assert!(
             amount <= sender_acc.stake_shares,
             "@{} not enough stNEAR balance {}",
@@ -613,18 +1039,92 @@ impl MetaPool {
         sender_acc.sub_stake_shares(amount, near_amount);
         receiver_acc.add_stake_shares(amount, near_amount);
 
-        self.internal_update_account(&sender_id, &sender_acc);
-        self.internal_update_account(&receiver_id, &receiver_acc);
+        self.internal_update_account(sender_id, &sender_acc);
+        self.internal_update_account(receiver_id, &receiver_acc);
 
         events::FtTransfer {
-            old_owner_id: &sender_id,
-            new_owner_id: &receiver_id,
+            old_owner_id: sender_id,
+            new_owner_id: receiver_id,
             amount: amount.into(),
             memo
         }
         .emit();
     }
 
+    /// transfer of NSLP shares (the LP token), see multi_token.rs
+    pub(crate) fn internal_nslp_shares_transfer(
+        &mut self,
+        sender_id: &AccountId,
+        receiver_id: &AccountId,
+        shares: u128,
+        memo: Option<&str>,
+    ) {
+        assert_ne!(
+            sender_id, receiver_id,
+            "Sender and receiver should be different"
+        );
+        assert!(shares > 0, "The amount should be a positive number");
+        let mut sender_acc = self.internal_get_account(sender_id);
+        let mut receiver_acc = self.internal_get_account(receiver_id);
+        assert!(
+            shares <= sender_acc.nslp_shares,
+            "@{} not enough NSLP shares {}",
+            sender_id,
+            sender_acc.nslp_shares
+        );
+        sender_acc.move_nslp_shares(&mut receiver_acc, shares);
+
+        self.internal_update_account(sender_id, &sender_acc);
+        self.internal_update_account(receiver_id, &receiver_acc);
+
+        events::MtTransfer {
+            old_owner_id: sender_id,
+            new_owner_id: receiver_id,
+            token_ids: vec![MT_TOKEN_NSLP],
+            amounts: vec![shares.into()],
+            memo,
+        }
+        .emit();
+    }
+
+    /// transfer of available NEAR between accounts, see multi_token.rs
+    /// total_available does not change
+    pub(crate) fn internal_available_transfer(
+        &mut self,
+        sender_id: &AccountId,
+        receiver_id: &AccountId,
+        amount: u128,
+        memo: Option<&str>,
+    ) {
+        assert_ne!(
+            sender_id, receiver_id,
+            "Sender and receiver should be different"
+        );
+        assert!(amount > 0, "The amount should be a positive number");
+        let mut sender_acc = self.internal_get_account(sender_id);
+        let mut receiver_acc = self.internal_get_account(receiver_id);
+        assert!(
+            amount <= sender_acc.available,
+            "@{} not enough available NEAR {}",
+            sender_id,
+            sender_acc.available
+        );
+        sender_acc.available -= amount;
+        receiver_acc.available += amount;
+
+        self.internal_update_account(sender_id, &sender_acc);
+        self.internal_update_account(receiver_id, &receiver_acc);
+
+        events::MtTransfer {
+            old_owner_id: sender_id,
+            new_owner_id: receiver_id,
+            token_ids: vec![MT_TOKEN_NEAR],
+            amounts: vec![amount.into()],
+            memo,
+        }
+        .emit();
+    }
+
     // ft_token, executed after ft_transfer_call,
     // resolves (maybe refunds)
     // TODO rename
@@ -665,9 +1165,9 @@ impl MetaPool {
                 self.internal_update_account(&sender_id, &sender_acc);
 
                 events::FtTransfer {
-                    old_owner_id: &sender_id,
-                    new_owner_id: &receiver_id,
-                    amount: amount.into(),
+                    old_owner_id: &receiver_id,
+                    new_owner_id: &sender_id,
+                    amount: refund_amount.into(),
                     memo: Some("refund")
                 }
                 .emit();
@@ -684,10 +1184,46 @@ impl MetaPool {
         (amount, 0)
     }
 
+    /// snapshot numbers still in the nslp_history ring buffer, oldest first
+    pub(crate) fn internal_nslp_history_range(&self) -> std::ops::Range<u64> {
+        self.nslp_history_count.saturating_sub(NSLP_HISTORY_EPOCHS)..self.nslp_history_count
+    }
+
+    /// writes over the oldest snapshot once the ring buffer is full
+    fn internal_record_nslp_snapshot(&mut self) {
+        let nslp_account = self.internal_get_nslp_account();
+        self.nslp_history.insert(
+            &(self.nslp_history_count % NSLP_HISTORY_EPOCHS),
+            &NslpEpochSnapshot {
+                epoch: env::epoch_height(),
+                share_price: self.amount_from_nslp_shares(ONE_E24, &nslp_account),
+                total_fees_retained: self.nslp_total_fees_retained,
+            },
+        );
+        self.nslp_history_count += 1;
+    }
+
     pub(crate) fn internal_end_of_epoch_clearing(&mut self) {
         self.assert_not_busy();
         // This method is called before any actual staking/unstaking.
 
+        // once per epoch, route the gas rebate
+        if self.gas_rebate_last_epoch < env::epoch_height() {
+            self.internal_route_gas_rebate();
+        }
+        // once per epoch, convert the fees of beneficiaries with auto_unstake
+        if self.fee_auto_unstake_last_epoch < env::epoch_height() {
+            self.internal_fee_auto_unstake();
+        }
+        // once per epoch, record the NSLP share price for get_nslp_apr
+        let last_snapshot = match self.nslp_history_count {
+            0 => None,
+            count => self.nslp_history.get(&((count - 1) % NSLP_HISTORY_EPOCHS)),
+        };
+        if last_snapshot.filter(|last| last.epoch >= env::epoch_height()).is_none() {
+            self.internal_record_nslp_snapshot();
+        }
+
         // if any one of the two is zero, we've a pure stake or pure unstake epoch, no clearing
         // just go and stake or unstake
         if self.epoch_stake_orders == 0 || self.epoch_unstake_orders == 0 {
@@ -708,13 +1244,193 @@ impl MetaPool {
         // clear opposing orders
         self.epoch_stake_orders -= to_keep;
         self.epoch_unstake_orders -= to_keep;
-        event!(r#"{{"event":"clr.ord","keep":"{}"}}"#, to_keep);
+        events::OrdersClearing {
+            keep: to_keep.into(),
+        }
+        .emit();
 
         // we will keep this NEAR (no need to go to the pools). We consider it reserved for unstake_claims, 4 epochs from now
         // or maybe some part could be put again in epoch_stake_orders to re-stake
         self.consider_retrieved_for_unstake_claims(to_keep);
     }
 
+    /// account receiving the fees of a fee beneficiary
+    pub(crate) fn fee_recipient_account_id(&self, recipient: &FeeRecipient) -> AccountId {
+        match recipient {
+            FeeRecipient::Operator => self.operator_account_id.clone(),
+            FeeRecipient::Treasury => self.treasury_account_id.clone(),
+            FeeRecipient::Account(account_id) => account_id.clone(),
+        }
+    }
+
+    /// keeps track of the fees received by a fee beneficiary account
+    /// this fn MUST NOT PANIC (called from add_extra_minted_shares)
+    pub(crate) fn internal_record_fee(
+        &mut self,
+        account_id: &AccountId,
+        rewards_fee_stnear: u128,
+        swap_cut_stnear: u128,
+    ) {
+        let mut accrual = self.fee_accruals.get(account_id).unwrap_or_default();
+        accrual.rewards_fee_stnear += rewards_fee_stnear;
+        accrual.swap_cut_stnear += swap_cut_stnear;
+        accrual.last_epoch = env::epoch_height();
+        if accrual.auto_unstake {
+            accrual.pending_auto_unstake += rewards_fee_stnear + swap_cut_stnear;
+        }
+        self.fee_accruals.insert(account_id, &accrual);
+    }
+
+    /// fee beneficiaries with auto_unstake: delayed-unstake the fee stNEAR received since the last time
+    /// called once per epoch, from internal_end_of_epoch_clearing
+    pub(crate) fn internal_fee_auto_unstake(&mut self) {
+        self.fee_auto_unstake_last_epoch = env::epoch_height();
+        for beneficiary in self.fee_beneficiaries.clone().iter() {
+            let account_id = self.fee_recipient_account_id(&beneficiary.recipient);
+            let mut accrual = match self.fee_accruals.get(&account_id) {
+                Some(accrual) if accrual.auto_unstake && accrual.pending_auto_unstake > 0 => accrual,
+                _ => continue,
+            };
+            let mut acc = match self.accounts.get(&account_id) {
+                Some(acc) => acc,
+                None => continue,
+            };
+            // the account could have transferred some stNEAR
+            let shares = std::cmp::min(accrual.pending_auto_unstake, acc.stake_shares);
+            if shares > 0 {
+                self.internal_unstake_shares(&account_id, &mut acc, shares);
+                accrual.auto_unstaked_stnear += shares;
+            }
+            accrual.pending_auto_unstake = 0;
+            self.fee_accruals.insert(&account_id, &accrual);
+        }
+    }
+
+    /// the beneficiary entry for recipient, added with 0 fees if not in the list
+    pub(crate) fn internal_get_fee_beneficiary_mut(
+        &mut self,
+        recipient: &FeeRecipient,
+    ) -> &mut FeeBeneficiary {
+        let inx = match self
+            .fee_beneficiaries
+            .iter()
+            .position(|x| &x.recipient == recipient)
+        {
+            Some(inx) => inx,
+            None => {
+                self.fee_beneficiaries.push(FeeBeneficiary {
+                    recipient: recipient.clone(),
+                    rewards_fee_bp: 0,
+                    swap_cut_bp: 0,
+                });
+                self.fee_beneficiaries.len() - 1
+            }
+        };
+        &mut self.fee_beneficiaries[inx]
+    }
+
+    /// fee beneficiaries limits: sum(rewards_fee_bp) <= 10%, sum(swap_cut_bp) < 100%,
+    /// developers entry at no less than its constant values
+    pub(crate) fn assert_fee_beneficiaries_valid(&self) {
+        let list = &self.fee_beneficiaries;
+        assert!(list.len() <= MAX_FEE_BENEFICIARIES, "too many fee beneficiaries");
+        let developers = FeeRecipient::Account(DEVELOPERS_ACCOUNT_ID.into());
+        assert!(
+            list.iter().any(|x| x.recipient == developers
+                && x.rewards_fee_bp >= DEVELOPERS_REWARDS_FEE_BASIS_POINTS
+                && x.swap_cut_bp >= DEVELOPERS_SWAP_CUT_BASIS_POINTS),
+            "the {} fee beneficiary is required, with rewards_fee_bp >= {} and swap_cut_bp >= {}",
+            DEVELOPERS_ACCOUNT_ID,
+            DEVELOPERS_REWARDS_FEE_BASIS_POINTS,
+            DEVELOPERS_SWAP_CUT_BASIS_POINTS
+        );
+        let mut total_rewards_fee: u32 = 0;
+        let mut total_swap_cut: u32 = 0;
+        for (inx, beneficiary) in list.iter().enumerate() {
+            if let FeeRecipient::Account(account_id) = &beneficiary.recipient {
+                // internal accounts can not receive fees
+                assert!(
+                    account_id != NSLP_INTERNAL_ACCOUNT && account_id != &env::current_account_id(),
+                    "internal account {} can not be a fee beneficiary",
+                    account_id
+                );
+                assert!(
+                    env::is_valid_account_id(account_id.as_bytes()),
+                    "invalid account {}",
+                    account_id
+                );
+            }
+            assert!(
+                list[..inx].iter().all(|x| x.recipient != beneficiary.recipient),
+                "duplicated fee beneficiary {:?}",
+                beneficiary.recipient
+            );
+            total_rewards_fee += beneficiary.rewards_fee_bp as u32;
+            total_swap_cut += beneficiary.swap_cut_bp as u32;
+        }
+        assert!(
+            total_rewards_fee <= MAX_REWARDS_FEE_BASIS_POINTS as u32,
+            "sum(rewards_fee_bp) {} can not be higher than {}",
+            total_rewards_fee,
+            MAX_REWARDS_FEE_BASIS_POINTS
+        );
+        assert!(
+            total_swap_cut < 10_000,
+            "sum(swap_cut_bp) {} must be lower than 10000",
+            total_swap_cut
+        );
+    }
+
+    /// routes the extra balance accumulated (30% of tx fees by near-protocol)
+    /// according to gas_rebate_split. Returns the amount routed
+    /// gas rebate that can be routed: balance not accounted in contract_account_balance,
+    /// minus the storage staking requirement and GAS_REBATE_STORAGE_BUFFER
+    pub(crate) fn internal_gas_rebate_available(&self) -> u128 {
+        let storage_cost = env::storage_usage() as u128 * env::storage_byte_cost();
+        env::account_balance().saturating_sub(
+            self.contract_account_balance + storage_cost + GAS_REBATE_STORAGE_BUFFER,
+        )
+    }
+
+    pub(crate) fn internal_route_gas_rebate(&mut self) -> u128 {
+        self.gas_rebate_last_epoch = env::epoch_height();
+        let extra_balance = self.internal_gas_rebate_available();
+        if extra_balance < ONE_NEAR {
+            //only if there's more than one near
+            return 0;
+        }
+        // left 10 cents (consider transfer fees)
+        let amount = extra_balance - 10 * NEAR_CENT;
+
+        let to_treasury = apply_pct(self.gas_rebate_split.treasury_bp, amount);
+        let to_staking = apply_pct(self.gas_rebate_split.staking_bp, amount);
+        let to_operator = amount - to_treasury - to_staking;
+
+        if to_operator > 0 {
+            Promise::new(self.operator_account_id.clone()).transfer(to_operator);
+        }
+        if to_treasury > 0 {
+            Promise::new(self.treasury_account_id.clone()).transfer(to_treasury);
+        }
+        if to_staking > 0 {
+            // NEAR remains in the contract and gets staked, no stNEAR minted => stNEAR price increases
+            self.contract_account_balance += to_staking;
+            self.epoch_stake_orders += to_staking;
+            self.total_for_staking += to_staking;
+        }
+        self.total_gas_rebate_routed += amount;
+
+        events::GasRebate {
+            amount: amount.into(),
+            operator: to_operator.into(),
+            treasury: to_treasury.into(),
+            staking: to_staking.into(),
+            total_routed: self.total_gas_rebate_routed.into(),
+        }
+        .emit();
+        amount
+    }
+
     /// if we have to add some funds to retrieved_for_unstake_claims
     /// this fn consider possible "extra" funds coming from rebalances and
     /// send those to epochs_stake_orders to be restaked
@@ -734,14 +1450,13 @@ impl MetaPool {
             self.epoch_stake_orders += extra; // put it in epoch_stake_orders, so the funds are re-staked before EOE
             self.unstaked_for_rebalance = self.unstaked_for_rebalance.saturating_sub(extra); // no longer waiting
 
-            //log event
-            event!(
-                r#"{{"event":"rebalance","extra":"{}","retrieved_for_unstake_claims":"{}","total_unstake_claims":"{}","ufr":"{}"}}"#,
-                extra,
-                self.retrieved_for_unstake_claims,
-                self.total_unstake_claims,
-                self.unstaked_for_rebalance,
-            );
+            events::Rebalance {
+                extra: extra.into(),
+                retrieved_for_unstake_claims: self.retrieved_for_unstake_claims.into(),
+                total_unstake_claims: self.total_unstake_claims.into(),
+                unstaked_for_rebalance: self.unstaked_for_rebalance.into(),
+            }
+            .emit();
         }
     }
 }
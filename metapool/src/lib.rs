@@ -1,6 +1,11 @@
 //! A smart contract that allows diversified staking, providing the stNEAR LST NEP-141 Token
 //! this contract include parts of core-contracts/lockup-contract & core-contracts/staking-pool
 
+// explicit `return` is the style of this crate
+#![allow(clippy::needless_return)]
+// ext_contract adds the promise params (account, deposit & gas) to the interface fns
+#![allow(clippy::too_many_arguments)]
+
 /********************************/
 /* CONTRACT Self Identification */
 /********************************/
@@ -15,7 +20,9 @@ const SOURCE_URL: &str = "github.com/Meta-Pool/liquid-staking-contract";
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
 use near_sdk::collections::{LookupMap, UnorderedMap};
 use near_sdk::json_types::Base58PublicKey;
-use near_sdk::{env, ext_contract, log, near_bindgen, AccountId, PanicOnDefault, Promise};
+use near_sdk::{
+    env, ext_contract, log, near_bindgen, AccountId, CryptoHash, PanicOnDefault, Promise,
+};
 
 //-- Sputnik DAO remote upgrade requires BLOCKCHAIN_INTERFACE low-level access
 #[cfg(target_arch = "wasm32")]
@@ -38,6 +45,8 @@ pub use crate::staking_pools::*;
 pub mod distribute;
 mod migrations;
 pub mod owner;
+pub mod reserves;
+pub mod timelock;
 
 pub mod reward_meter;
 pub use reward_meter::*;
@@ -45,6 +54,10 @@ pub use reward_meter::*;
 pub mod empty_nep_145;
 pub mod events;
 pub mod fungible_token_standard;
+pub mod multi_token;
+
+#[cfg(test)]
+mod test_utils;
 
 // setup_alloc adds a #[cfg(target_arch = "wasm32")] to the global allocator, which prevents the allocator
 // from being used when the contract's main file is used in simulation testing.
@@ -81,7 +94,7 @@ pub trait ExtMetaStakingPoolOwnerCallbacks {
         #[callback] unstaked_balance: U128String,
     );
 
-    fn after_minting_meta(self, account_id: AccountId, to_mint: U128String);
+    fn on_verify_reserves(&mut self, from_index: u16, account_ids: Vec<AccountId>);
 }
 
 // #[ext_contract(meta_token_mint)]
@@ -165,9 +178,6 @@ pub struct MetaPool {
     // when someone "unstakes" they "burns" X shares at current price to recoup Y near
     pub total_stake_shares: u128, //total stNEAR minted
 
-    /// META (now mpDAO) is the governance token
-    pub total_meta: u128, // deprecated
-
     /// The total amount of tokens actually unstaked and in the waiting-delay (the tokens are in the staking pools)
     /// equivalent to sum(sp.unstaked)
     pub total_unstaked_and_waiting: u128,
@@ -202,49 +212,43 @@ pub struct MetaPool {
     // We assume this pool is always UNBALANCED, there should be more NEAR than stNEAR 99% of the time
     ///NEAR/stNEAR Liquidity target. If the Liquidity reach this amount, the fee reaches nslp_min_discount_basis_points
     pub nslp_liquidity_target: u128, // 150_000*NEAR initially
+    ///if set, the Liquidity target is a % of total_for_staking and nslp_liquidity_target is not used
+    pub nslp_relative_target: Option<NslpRelativeTarget>,
     ///NEAR/stNEAR Liquidity pool max fee
     pub nslp_max_discount_basis_points: u16, //5% initially
     ///NEAR/stNEAR Liquidity pool min fee
     pub nslp_min_discount_basis_points: u16, //0.5% initially
-
-    // (deprecated) The next 3 values define meta rewards multipliers. (10 => 1x, 20 => 2x, ...)
-    // for each stNEAR paid staking reward, reward stNEAR holders with META. default:5x. reward META = rewards * (mult_pct*10) / 100
-    pub staker_meta_mult_pct: u16, // deprecated
-    // for each stNEAR paid as discount, reward stNEAR sellers with META. default:1x. reward META = discounted * (mult_pct*10) / 100
-    pub stnear_sell_meta_mult_pct: u16, // deprecated
-    // for each stNEAR paid as discount, reward LP providers  with META. default:20x. reward META = fee * (mult_pct*10) / 100
-    pub lp_provider_meta_mult_pct: u16, // deprecated
+    ///shape of the fee curve between max and min
+    pub nslp_fee_curve: NslpFeeCurve,
+    ///discount of the NSLP stNEAR sold by buy_stnear
+    pub nslp_buy_discount_basis_points: u16,
+    ///NEAR value of the liquid-unstake fees retained by the NSLP (after the fee beneficiaries cuts)
+    pub nslp_total_fees_retained: u128,
+    ///one snapshot per epoch, ring buffer of the last NSLP_HISTORY_EPOCHS epochs
+    ///keyed by (snapshot number % NSLP_HISTORY_EPOCHS), see get_nslp_apr
+    pub nslp_history: LookupMap<u64, NslpEpochSnapshot>,
+    ///snapshots recorded since the start, the next one goes to slot (nslp_history_count % NSLP_HISTORY_EPOCHS)
+    pub nslp_history_count: u64,
 
     /// min amount accepted as deposit or stake
     pub min_deposit_amount: u128,
 
     /// Operator account ID (who's in charge to call distribute_xx() on a periodic basis)
     pub operator_account_id: AccountId,
-    /// operator_rewards_fee_basis_points. (0.2% default) 100 basis point => 1%. E.g.: owner_fee_basis_points=30 => 0.3% owner's fee
-    pub operator_rewards_fee_basis_points: u16,
-    /// owner's cut on Liquid Unstake fee (3% default)
-    pub operator_swap_cut_basis_points: u16,
     /// Treasury account ID (it will be controlled by a DAO on phase II)
     pub treasury_account_id: AccountId,
-    /// treasury cut on Liquid Unstake (25% from the fees by default)
-    pub treasury_swap_cut_basis_points: u16,
+    /// rewards fee & liquid-unstake fee cuts, by default: operator, treasury & developers
+    /// sum(rewards_fee_bp) <= 10%, sum(swap_cut_bp) < 100%
+    pub fee_beneficiaries: Vec<FeeBeneficiary>,
+    /// fees received by each fee beneficiary account
+    pub fee_accruals: LookupMap<AccountId, FeeAccrual>,
+    /// epoch of the last fee auto unstake, see internal_fee_auto_unstake
+    pub fee_auto_unstake_last_epoch: EpochHeight,
 
     // Configurable info for [NEP-129](https://github.com/nearprotocol/NEPs/pull/129)
     pub web_app_url: Option<String>,
     pub auditor_account_id: Option<AccountId>,
 
-    /// (deprecated) Where's the governance token contract
-    pub meta_token_account_id: AccountId, // deprecated
-
-    /// (deprecated) estimated & max meta rewards for each category
-    pub est_meta_rewards_stakers: u128,
-    pub est_meta_rewards_lu: u128, //liquid-unstakers
-    pub est_meta_rewards_lp: u128, //liquidity-providers
-    // max. when this amount is passed, corresponding multiplier is damped proportionally
-    pub max_meta_rewards_stakers: u128,
-    pub max_meta_rewards_lu: u128, //liquid-unstakers
-    pub max_meta_rewards_lp: u128, //liquidity-providers
-
     /// up to 1% of the total pool can be unstaked for rebalance (no more than 1% to not affect APY)
     pub unstake_for_rebalance_cap_bp: u16, // default 100bp, meaning 1%
     /// when some unstake for rebalance is executed, this amount is increased
@@ -252,6 +256,49 @@ pub struct MetaPool {
     /// represents the amount that's not staked because is in transit for rebalance.
     /// it could be in unstaked_and_waiting or in the contract & epoch_stake_orders
     pub unstaked_for_rebalance: u128,
+
+    /// lockup proxy contracts authorized to call stake_for_lockup, unstake_from_lockup_shares & withdraw_to_lockup
+    pub lockup_contracts: Vec<AccountId>,
+    /// account-id suffixes identifying lockup accounts (e.g. ".lockup.near")
+    /// lockup accounts can only operate thru an authorized lockup contract
+    pub lockup_account_suffixes: Vec<String>,
+
+    /// operations paused during an incident, bitmask of PAUSE_* constants
+    pub paused_operations: u32,
+
+    /// granted roles by account (access control), see enum Role
+    pub roles: UnorderedMap<AccountId, Vec<Role>>,
+
+    /// delay, per admin action class, between queue_xxx and execute_admin_action
+    pub timelock_delays: TimelockDelays,
+    /// queued admin actions waiting for their timelock delay
+    pub pending_admin_actions: Vec<StoredPendingAdminAction>,
+    pub next_admin_action_id: u32,
+
+    /// proposed key accounts, waiting for the new account to accept
+    pub pending_owner: Option<PendingAccountTransfer>,
+    pub pending_operator: Option<PendingAccountTransfer>,
+    pub pending_treasury: Option<PendingAccountTransfer>,
+
+    /// code hash staged for the next upgrade, see stage_upgrade
+    pub staged_upgrade: Option<StagedUpgrade>,
+
+    /// how the gas rebate (extra balance accumulated) is routed, see route_gas_rebate
+    pub gas_rebate_split: GasRebateSplit,
+    /// epoch of the last automatic routing
+    pub gas_rebate_last_epoch: EpochHeight,
+    /// cumulative gas rebate routed (operator + treasury + staking)
+    pub total_gas_rebate_routed: u128,
+
+    /// last proof of reserves, see verify_reserves
+    pub reserves_snapshot: Option<ReservesSnapshot>,
+
+    /// version of the state layout & values, see migrations.rs
+    pub state_version: u16,
+    /// all entries in `accounts` are stored in this layout version (ACCOUNT_LAYOUT_VERSION) or newer
+    pub accounts_version: u16,
+    /// progress of the migrate_accounts sweep
+    pub accounts_migration_index: u64,
 }
 
 #[near_bindgen]
@@ -273,7 +320,9 @@ impl MetaPool {
 
     /// Initializes MetaPool contract.
     /// - `owner_account_id` - the account ID of the owner.  Only this account can call owner's methods on this contract.
+    /// - `meta_token_account_id` - (deprecated) ignored, kept for init call compat
     #[init]
+    #[allow(unused_variables)]
     pub fn new(
         owner_account_id: AccountId,
         treasury_account_id: AccountId,
@@ -288,9 +337,9 @@ impl MetaPool {
             contract_account_balance: 0,
             web_app_url: Some(String::from(DEFAULT_WEB_APP_URL)),
             auditor_account_id: Some(String::from(DEFAULT_AUDITOR_ACCOUNT_ID)),
-            operator_rewards_fee_basis_points: DEFAULT_OPERATOR_REWARDS_FEE_BASIS_POINTS,
-            operator_swap_cut_basis_points: DEFAULT_OPERATOR_SWAP_CUT_BASIS_POINTS,
-            treasury_swap_cut_basis_points: DEFAULT_TREASURY_SWAP_CUT_BASIS_POINTS,
+            fee_beneficiaries: default_fee_beneficiaries(),
+            fee_accruals: LookupMap::new(b"F".to_vec()),
+            fee_auto_unstake_last_epoch: 0,
             staking_paused: false,
             total_available: 0,
             total_for_staking: 0,
@@ -303,43 +352,60 @@ impl MetaPool {
             epoch_last_clearing: 0,
             accumulated_staked_rewards: 0,
             total_stake_shares: 0,
-            total_meta: 0,
             accounts: UnorderedMap::new(b"A".to_vec()),
             loan_requests: LookupMap::new(b"L".to_vec()),
             nslp_liquidity_target: 10_000 * NEAR,
+            nslp_relative_target: None,
             nslp_max_discount_basis_points: 180, //1.8%
             nslp_min_discount_basis_points: 25,  //0.25%
+            nslp_fee_curve: NslpFeeCurve::Linear,
+            nslp_buy_discount_basis_points: DEFAULT_NSLP_BUY_DISCOUNT_BASIS_POINTS,
+            nslp_total_fees_retained: 0,
+            nslp_history: LookupMap::new(b"H".to_vec()),
+            nslp_history_count: 0,
             min_deposit_amount: 10 * NEAR,
-            // (deprecated) for each stNEAR paid as discount, reward stNEAR sellers with META. initial 5x, default:1x. reward META = discounted * mult_pct / 100
-            stnear_sell_meta_mult_pct: 50, //5x (deprecated)
-            // (deprecated) for each stNEAR paid staking reward, reward stNEAR holders with META. initial 10x, default:5x. reward META = rewards * mult_pct / 100
-            staker_meta_mult_pct: 5000, //500x (deprecated)
-            // for each stNEAR paid as discount, reward LPs with META. initial 50x, default:20x. reward META = fee * mult_pct / 100
-            lp_provider_meta_mult_pct: 200, //20x (deprecated)
             staking_pools: Vec::new(),
-            meta_token_account_id,
-            est_meta_rewards_stakers: 0, // (deprecated)
-            est_meta_rewards_lu: 0,      // (deprecated)
-            est_meta_rewards_lp: 0,      // (deprecated)
-            max_meta_rewards_stakers: 1_000_000 * ONE_NEAR, // (deprecated)
-            max_meta_rewards_lu: 50_000 * ONE_NEAR, // (deprecated)
-            max_meta_rewards_lp: 100_000 * ONE_NEAR, // (deprecated)
             unstaked_for_rebalance: 0,
             unstake_for_rebalance_cap_bp: 100,
+            lockup_contracts: default_lockup_contracts(),
+            lockup_account_suffixes: default_lockup_account_suffixes(),
+            paused_operations: 0,
+            roles: UnorderedMap::new(b"R".to_vec()),
+            // no timelock on a fresh deploy, the owner raises delays with set_timelock_delay
+            timelock_delays: TimelockDelays::none(),
+            pending_admin_actions: Vec::new(),
+            next_admin_action_id: 1,
+            pending_owner: None,
+            pending_operator: None,
+            pending_treasury: None,
+            staged_upgrade: None,
+            gas_rebate_split: GasRebateSplit::default(),
+            gas_rebate_last_epoch: 0,
+            total_gas_rebate_routed: 0,
+            reserves_snapshot: None,
+            state_version: STATE_VERSION,
+            accounts_version: ACCOUNT_LAYOUT_VERSION,
+            accounts_migration_index: 0,
         };
         //all key accounts must be different
         result.assert_key_accounts_are_different();
+        migrations::write_stored_state_version(STATE_VERSION);
         return result;
     }
 
     fn assert_key_accounts_are_different(&self) {
         //all accounts must be different
         assert!(self.owner_account_id != self.operator_account_id);
-        assert!(self.owner_account_id != DEVELOPERS_ACCOUNT_ID);
         assert!(self.owner_account_id != self.treasury_account_id);
-        assert!(self.operator_account_id != DEVELOPERS_ACCOUNT_ID);
         assert!(self.operator_account_id != self.treasury_account_id);
-        assert!(self.treasury_account_id != DEVELOPERS_ACCOUNT_ID);
+        // fee beneficiaries with a fixed account can not be a key account
+        for beneficiary in self.fee_beneficiaries.iter() {
+            if let FeeRecipient::Account(account_id) = &beneficiary.recipient {
+                assert!(&self.owner_account_id != account_id);
+                assert!(&self.operator_account_id != account_id);
+                assert!(&self.treasury_account_id != account_id);
+            }
+        }
     }
 
     //------------------------------------
@@ -359,12 +425,12 @@ impl MetaPool {
 
     /// Withdraws from "UNSTAKED" balance *TO MIMIC core-contracts/staking-pool* .- core-contracts/staking-pool only has "unstaked" to withdraw from
     pub fn withdraw(&mut self, amount: U128String) -> Promise {
-        assert_not_lockup_account_calling();
+        self.assert_not_lockup_account_calling();
         self.internal_withdraw_use_unstaked(&env::predecessor_account_id(), amount.0)
     }
     /// Withdraws ALL from from "UNSTAKED" balance *TO MIMIC core-contracts/staking-pool .- core-contracts/staking-pool only has "unstaked" to withdraw from
     pub fn withdraw_all(&mut self) -> Promise {
-        assert_not_lockup_account_calling();
+        self.assert_not_lockup_account_calling();
         let account_id = env::predecessor_account_id();
         let account = self.internal_get_account(&account_id);
         self.internal_withdraw_use_unstaked(&account_id, account.unstaked)
@@ -374,7 +440,7 @@ impl MetaPool {
     /// completes delayed-unstake action by transferring from retrieved_from_the_pools to user's NEAR account
     /// equivalent to core-contracts/staking-pool.withdraw_all, used by metastaking webapp
     pub fn withdraw_unstaked(&mut self) -> Promise {
-        assert_not_lockup_account_calling();
+        self.assert_not_lockup_account_calling();
         let account_id = env::predecessor_account_id();
         let account = self.internal_get_account(&account_id);
         self.internal_withdraw_use_unstaked(&account_id, account.unstaked)
@@ -383,7 +449,8 @@ impl MetaPool {
     /// Deposits the attached amount into the inner account of the predecessor and stakes it.
     #[payable]
     pub fn deposit_and_stake(&mut self) -> U128String {
-        assert_not_lockup_account_calling();
+        self.assert_not_lockup_account_calling();
+        self.assert_operation_not_paused(PAUSE_DEPOSIT);
         let account_id = env::predecessor_account_id();
         let amount = self.internal_deposit(&account_id);
         let shares = self.internal_stake_from_account(&account_id, amount);
@@ -391,13 +458,6 @@ impl MetaPool {
         // check if the liquidity pool needs liquidity, and then use this opportunity to liquidate stnear in the LP by internal-clearing
         // the amount just deposited, might be swapped in the liquid-unstake pool
         self.nslp_try_internal_clearing(amount);
-        events::FtMint {
-            owner_id: &account_id,
-            amount: shares.into(),
-            memo: None,
-        }
-        .emit();
-
         shares.into()
     }
 
@@ -417,7 +477,8 @@ impl MetaPool {
     /// Unstakes all staked balance from the inner account of the predecessor.
     /// The new total unstaked balance will be available for withdrawal in four epochs.
     pub fn unstake_all(&mut self) {
-        assert_not_lockup_account_calling();
+        self.assert_not_lockup_account_calling();
+        self.assert_operation_not_paused(PAUSE_DELAYED_UNSTAKE);
         let account_id = env::predecessor_account_id();
         let mut account = self.internal_get_account(&account_id);
         let all_shares = account.stake_shares;
@@ -429,7 +490,8 @@ impl MetaPool {
     /// The new total unstaked balance will be available for withdrawal in four epochs.
     /// delayed_unstake, amount_requested is in yoctoNEARs
     pub fn unstake(&mut self, amount: U128String) {
-        assert_not_lockup_account_calling();
+        self.assert_not_lockup_account_calling();
+        self.assert_operation_not_paused(PAUSE_DELAYED_UNSTAKE);
         self.internal_unstake(&env::predecessor_account_id(), amount.0);
     }
 
@@ -438,7 +500,8 @@ impl MetaPool {
     /*******************/
     #[payable]
     pub fn stake_for_lockup(&mut self, lockup_account_id: String) -> U128String {
-        assert_lockup_contract_calling();
+        self.assert_lockup_contract_calling();
+        self.assert_operation_not_paused(PAUSE_DEPOSIT);
         let amount = self.internal_deposit(&lockup_account_id);
         let shares = self.internal_stake_from_account(&lockup_account_id, amount);
         //----------
@@ -456,17 +519,32 @@ impl MetaPool {
         lockup_account_id: String,
         shares: U128String,
     ) -> (U128String, U64String) {
-        assert_lockup_contract_calling();
+        self.assert_lockup_contract_calling();
+        self.assert_operation_not_paused(PAUSE_DELAYED_UNSTAKE);
         let mut acc = self.internal_get_account(&lockup_account_id);
         let (nears, epoch) = self.internal_unstake_shares(&lockup_account_id, &mut acc, shares.0);
         (nears.into(), epoch.into())
     }
 
     pub fn withdraw_to_lockup(&mut self, lockup_account_id: String, amount: U128String) -> Promise {
-        assert_lockup_contract_calling();
+        self.assert_lockup_contract_calling();
         self.internal_withdraw_use_unstaked(&lockup_account_id, amount.0)
     }
 
+    /// Liquid-unstakes the exact amount of shares from a lockup account
+    /// same NSLP swap & fee split as liquid_unstake, the NEAR is sent back to the lockup account
+    /// returns nears transferred
+    pub fn liquid_unstake_for_lockup(
+        &mut self,
+        lockup_account_id: String,
+        shares: U128String,
+        min_expected_near: U128String,
+    ) -> LiquidUnstakeResult {
+        self.assert_lockup_contract_calling();
+        self.assert_operation_not_paused(PAUSE_LIQUID_UNSTAKE);
+        self.internal_liquid_unstake(&lockup_account_id, shares.0, min_expected_near.0)
+    }
+
     /*****************************/
     /* staking-pool View methods */
     /*****************************/
@@ -513,23 +591,44 @@ impl MetaPool {
     /// Returns the current reward fee as a fraction.
     pub fn get_reward_fee_fraction(&self) -> RewardFeeFraction {
         return RewardFeeFraction {
-            numerator: (self.operator_rewards_fee_basis_points
-                + DEVELOPERS_REWARDS_FEE_BASIS_POINTS)
-                .into(),
+            numerator: self.get_reward_fee_bp().into(),
             denominator: 10_000,
         };
     }
+    /// sum of the rewards fee of all fee beneficiaries
     pub fn get_reward_fee_bp(&self) -> u16 {
-        self.operator_rewards_fee_basis_points + DEVELOPERS_REWARDS_FEE_BASIS_POINTS
+        self.fee_beneficiaries
+            .iter()
+            .map(|x| x.rewards_fee_bp)
+            .sum()
     }
 
+    /// timelocked, see queue_set_reward_fee
     #[payable]
     pub fn set_reward_fee(&mut self, basis_points: u16) {
-        self.assert_owner_calling();
+        self.assert_role(Role::FeeManager);
         assert_one_yocto();
-        assert!(basis_points <= 1000); // less than or equal 10%
-        self.operator_rewards_fee_basis_points =
-            basis_points.saturating_sub(DEVELOPERS_REWARDS_FEE_BASIS_POINTS);
+        self.assert_not_timelocked(AdminActionClass::RewardFee);
+        self.internal_set_reward_fee(basis_points);
+    }
+    pub(crate) fn internal_set_reward_fee(&mut self, basis_points: u16) {
+        assert!(basis_points <= MAX_REWARDS_FEE_BASIS_POINTS); // less than or equal 10%
+        // basis_points is the total fee, the operator gets what's left after the other beneficiaries
+        let others: u16 = self
+            .fee_beneficiaries
+            .iter()
+            .filter(|x| x.recipient != FeeRecipient::Operator)
+            .map(|x| x.rewards_fee_bp)
+            .sum();
+        self.internal_get_fee_beneficiary_mut(&FeeRecipient::Operator)
+            .rewards_fee_bp = basis_points.saturating_sub(others);
+        events::AdminChange {
+            method: "set_reward_fee",
+            by: &env::predecessor_account_id(),
+            account_id: None,
+            value: Some(basis_points.to_string()),
+        }
+        .emit();
     }
 
     /// Returns the staking public key
@@ -605,180 +704,225 @@ impl MetaPool {
         return self.internal_get_discount_basis_points(lp_account.available, stnear_to_sell.0);
     }
 
-    /// user method
-    /// swaps stNEAR->NEAR in the Liquidity Pool
-    /// returns nears transferred
-    //#[payable]
-    pub fn liquid_unstake(
-        &mut self,
-        st_near_to_burn: U128String,
-        min_expected_near: U128String,
-    ) -> LiquidUnstakeResult {
+    /// user method - NEAR->stNEAR
+    /// buys stNEAR with the attached NEAR. The stNEAR held by the NSLP is sold first,
+    /// at nslp_buy_discount_basis_points below its value, the rest is minted as in deposit_and_stake
+    #[payable]
+    pub fn buy_stnear(&mut self, min_expected_st_near: U128String) -> BuyStNearResult {
         self.assert_not_busy();
-        // Q: Why not? - R: liquid_unstake It's not as problematic as transfer, because it moves tokens between accounts of the same user
-        // so let's remove the one_yocto_requirement, waiting for a better solution for the function-call keys NEP-141 problem
-        //assert_one_yocto();
-
+        self.assert_not_lockup_account_calling();
+        self.assert_operation_not_paused(PAUSE_BUY_STNEAR);
         let account_id = env::predecessor_account_id();
-        let mut user_account = self.internal_get_account(&account_id);
-
-        let stnear_owned = user_account.stake_shares;
+        let amount = self.internal_deposit(&account_id);
 
-        let st_near_to_sell:u128 =
-        // if the amount is close to user's total, remove user's total
-        // to: a) do not leave less than ONE_MILLI_NEAR in the account, b) Allow 10 yoctos of rounding, e.g. remove(100) removes 99.999993 without panicking
-        if is_close(st_near_to_burn.0, stnear_owned) { // allow for rounding simplification
-            stnear_owned
+        let (near_to_nslp, st_near_from_nslp) = self.internal_quote_buy_stnear(amount);
+        if st_near_from_nslp > 0 {
+            let mut acc = self.internal_get_account(&account_id);
+            let mut nslp_account = self.internal_get_nslp_account();
+            // NEAR moves from the user's available to the NSLP's available, total_available does not change
+            acc.available -= near_to_nslp;
+            nslp_account.available += near_to_nslp;
+            nslp_account.sub_st_near(st_near_from_nslp, self);
+            acc.add_st_near(st_near_from_nslp, self);
+            self.internal_update_account(&account_id, &acc);
+            self.internal_save_nslp_account(&nslp_account);
+
+            events::BuyStNear {
+                account_id: &account_id,
+                near: near_to_nslp.into(),
+                stnear: st_near_from_nslp.into(),
+            }
+            .emit();
+            events::FtTransfer {
+                old_owner_id: &NSLP_INTERNAL_ACCOUNT.into(),
+                new_owner_id: &account_id,
+                amount: st_near_from_nslp.into(),
+                memo: Some("buy_stnear"),
+            }
+            .emit();
+            events::MtTransfer {
+                old_owner_id: &account_id,
+                new_owner_id: &NSLP_INTERNAL_ACCOUNT.into(),
+                token_ids: vec![MT_TOKEN_NEAR],
+                amounts: vec![near_to_nslp.into()],
+                memo: Some("buy_stnear"),
+            }
+            .emit();
         }
-        else  {
-            st_near_to_burn.0
-        };
 
-        log!(
-            "st_near owned:{}, to_sell:{}",
-            user_account.stake_shares,
-            st_near_to_sell
-        );
-
-        assert!(
-            stnear_owned >= st_near_to_sell,
-            "Not enough stNEAR. You own {}",
-            stnear_owned
-        );
-
-        let mut nslp_account = self.internal_get_nslp_account();
-
-        //compute how many nears are the st_near valued at
-        let nears_out = self.amount_from_stake_shares(st_near_to_sell);
-        let swap_fee_basis_points =
-            self.internal_get_discount_basis_points(nslp_account.available, nears_out);
-        assert!(swap_fee_basis_points < 10000, "inconsistency d>1");
-        let fee = apply_pct(swap_fee_basis_points, nears_out);
+        // the NSLP has no more stNEAR, mint the rest. No internal clearing needed
+        let rest = amount - near_to_nslp;
+        let (minted, near_available) = if rest >= self.internal_min_stake_from_account() {
+            (self.internal_stake_from_account(&account_id, rest), 0)
+        } else {
+            (0, rest)
+        };
 
-        let near_to_receive = nears_out - fee;
+        let st_near = st_near_from_nslp + minted;
         assert!(
-            near_to_receive >= min_expected_near.0,
+            st_near >= min_expected_st_near.0,
             "Price changed, your min amount {} is not satisfied {}. Try again",
-            min_expected_near.0,
-            near_to_receive
-        );
-        assert!(
-            nslp_account.available >= near_to_receive,
-            "Not enough liquidity in the liquidity pool"
-        );
-
-        //the NEAR for the user comes from the LP
-        nslp_account.available -= near_to_receive;
-        user_account.available += near_to_receive;
-
-        // compute how many shares the swap fee represent
-        let fee_in_st_near = self.stake_shares_from_amount(fee);
-
-        // involved accounts
-        assert!(
-            &account_id != &self.treasury_account_id,
-            "can't use treasury account"
-        );
-        let mut treasury_account = self
-            .accounts
-            .get(&self.treasury_account_id)
-            .unwrap_or_default();
-        assert!(
-            &account_id != &self.operator_account_id,
-            "can't use operator account"
-        );
-        let mut operator_account = self
-            .accounts
-            .get(&self.operator_account_id)
-            .unwrap_or_default();
-        assert!(
-            &account_id != &DEVELOPERS_ACCOUNT_ID,
-            "can't use developers account"
-        );
-        let mut developers_account = self
-            .accounts
-            .get(&DEVELOPERS_ACCOUNT_ID.into())
-            .unwrap_or_default();
-
-        // The treasury cut in stnear-shares (25% by default)
-        let treasury_st_near_cut = apply_pct(self.treasury_swap_cut_basis_points, fee_in_st_near);
-        treasury_account.add_st_near(treasury_st_near_cut, &self);
-
-        // The cut that the contract owner (operator) takes. (3% of 1% normally)
-        let operator_st_near_cut = apply_pct(self.operator_swap_cut_basis_points, fee_in_st_near);
-        operator_account.add_st_near(operator_st_near_cut, &self);
-
-        // The cut that the developers take. (2% of 1% normally)
-        let developers_st_near_cut = apply_pct(DEVELOPERS_SWAP_CUT_BASIS_POINTS, fee_in_st_near);
-        developers_account.add_st_near(developers_st_near_cut, &self);
-
-        log!("treasury_st_near_cut:{} operator_st_near_cut:{} developers_st_near_cut:{} fee_in_st_near:{}",
-            treasury_st_near_cut,operator_st_near_cut,developers_st_near_cut,fee_in_st_near);
-
-        assert!(
-            fee_in_st_near > treasury_st_near_cut + developers_st_near_cut + operator_st_near_cut
+            min_expected_st_near.0,
+            st_near
         );
+        BuyStNearResult {
+            st_near: st_near.into(),
+            from_nslp: st_near_from_nslp.into(),
+            near_available: near_available.into(),
+        }
+    }
 
-        // The rest of the st_near sold goes into the liq-pool. Because it is a larger amount than NEARs removed, it will increase share value for all LP providers.
-        // Adding value to the pool via adding more stNEAR value than the NEAR removed
-        let st_near_to_liq_pool = st_near_to_sell
-            - (treasury_st_near_cut + operator_st_near_cut + developers_st_near_cut);
-        log!("nslp_account.add_st_near {}", st_near_to_liq_pool);
-        // major part of stNEAR sold goes to the NSLP
-        nslp_account.add_st_near(st_near_to_liq_pool, &self);
-
-        //complete the transfer, remove stnear from the user (stnear was transferred to the LP & others)
-        user_account.sub_st_near(st_near_to_sell, &self);
-
-        //Save involved accounts
-        self.internal_update_account(&self.treasury_account_id.clone(), &treasury_account);
-        self.internal_update_account(&self.operator_account_id.clone(), &operator_account);
-        self.internal_update_account(&DEVELOPERS_ACCOUNT_ID.into(), &developers_account);
-        //Save nslp accounts
-        self.internal_save_nslp_account(&nslp_account);
+    /// stNEAR received for near_amount by minting (deposit_and_stake) vs buy_stnear
+    pub fn get_buy_stnear_quote(&self, near_amount: U128String) -> BuyStNearQuoteJSON {
+        let (near_to_nslp, st_near_from_nslp) = self.internal_quote_buy_stnear(near_amount.0);
+        let rest = near_amount.0 - near_to_nslp;
+        let (minted, near_available) = if rest >= self.internal_min_stake_from_account() {
+            (self.stake_shares_from_amount(rest), 0)
+        } else {
+            (0, rest)
+        };
+        BuyStNearQuoteJSON {
+            mint_st_near: self.stake_shares_from_amount(near_amount.0).into(),
+            buy_st_near: (st_near_from_nslp + minted).into(),
+            from_nslp: st_near_from_nslp.into(),
+            near_to_nslp: near_to_nslp.into(),
+            near_available: near_available.into(),
+            discount_basis_points: self.nslp_buy_discount_basis_points,
+        }
+    }
 
-        //simplified user-flow
-        //direct transfer to user (instead of leaving it in-contract as "available")
-        let transfer_amount = user_account.take_from_available(&account_id, near_to_receive, self);
-        self.native_transfer(&account_id, transfer_amount);
+    /// NSLP position of a liquidity provider
+    pub fn get_lp_position(&self, account_id: AccountId) -> LpPositionJSON {
+        let acc = self.accounts.get(&account_id).unwrap_or_default();
+        let value = acc.valued_nslp_shares(self, &self.internal_get_nslp_account());
+        let cost_basis = if acc.is_nslp_cost_basis_tracked() {
+            Some(acc.nslp_cost_basis)
+        } else {
+            None // opened before the cost basis was tracked
+        };
+        LpPositionJSON {
+            account_id,
+            nslp_shares: acc.nslp_shares.into(),
+            value: value.into(),
+            cost_basis: cost_basis.map(|x| x.into()),
+            earned: cost_basis.map(|x| value.saturating_sub(x).into()),
+        }
+    }
 
-        //Save user account
-        self.internal_update_account(&account_id, &user_account);
+    /// NSLP APR, from the share price growth in the last `epochs` epochs
+    /// (or less, if there's not enough history)
+    pub fn get_nslp_apr(&self, epochs: u64) -> NslpAprJSON {
+        let current_epoch = env::epoch_height();
+        let from = self
+            .internal_nslp_history_range()
+            .filter_map(|inx| self.nslp_history.get(&(inx % NSLP_HISTORY_EPOCHS)))
+            .find(|x| {
+                x.epoch.saturating_add(epochs) >= current_epoch
+                    && x.epoch < current_epoch
+                    && x.share_price > 0
+            });
+        let (measured_epochs, apr_basis_points, fees_retained) = match from {
+            Some(snapshot) => {
+                let elapsed = current_epoch - snapshot.epoch;
+                let share_price =
+                    self.amount_from_nslp_shares(ONE_E24, &self.internal_get_nslp_account());
+                let growth = share_price.saturating_sub(snapshot.share_price);
+                let apr_bp = proportional(
+                    growth * 10_000,
+                    EPOCHS_PER_YEAR,
+                    snapshot.share_price * elapsed as u128,
+                );
+                (
+                    elapsed,
+                    std::cmp::min(apr_bp, u32::MAX as u128) as u32,
+                    self.nslp_total_fees_retained - snapshot.total_fees_retained,
+                )
+            }
+            None => (0, 0, 0),
+        };
+        NslpAprJSON {
+            epochs: measured_epochs.into(),
+            apr_basis_points,
+            fees_retained: fees_retained.into(),
+            total_fees_retained: self.nslp_total_fees_retained.into(),
+        }
+    }
 
-        log!(
-            "@{} liquid-unstaked {} stNEAR, got {} NEAR",
-            &account_id,
-            st_near_to_sell,
-            transfer_amount
-        );
-        event!(
-            r#"{{"event":"LIQ.U","account_id":"{}","stnear":"{}","near":"{}"}}"#,
-            &account_id,
-            st_near_to_sell,
-            transfer_amount
-        );
+    /// user method
+    /// swaps stNEAR->NEAR in the Liquidity Pool
+    /// returns nears transferred
+    //#[payable]
+    pub fn liquid_unstake(
+        &mut self,
+        st_near_to_burn: U128String,
+        min_expected_near: U128String,
+    ) -> LiquidUnstakeResult {
+        self.liquid_unstake_guarded(st_near_to_burn, min_expected_near, None)
+    }
 
-        return LiquidUnstakeResult {
-            near: transfer_amount.into(),
-            fee: fee_in_st_near.into(),
-            meta: 0.into(), // meta_to_seller.into(),
-        };
+    /// user method
+    /// same as liquid_unstake, but panics if the tx is included after deadline_block_height
+    pub fn liquid_unstake_guarded(
+        &mut self,
+        st_near_to_burn: U128String,
+        min_expected_near: U128String,
+        deadline_block_height: Option<U64String>,
+    ) -> LiquidUnstakeResult {
+        // Q: Why not? - R: liquid_unstake It's not as problematic as transfer, because it moves tokens between accounts of the same user
+        // so let's remove the one_yocto_requirement, waiting for a better solution for the function-call keys NEP-141 problem
+        //assert_one_yocto();
+        self.assert_operation_not_paused(PAUSE_LIQUID_UNSTAKE);
+        self.assert_deadline_not_passed(deadline_block_height);
+        self.internal_liquid_unstake(
+            &env::predecessor_account_id(),
+            st_near_to_burn.0,
+            min_expected_near.0,
+        )
     }
 
     /// add liquidity - payable
+    /// no slippage protection, see nslp_add_liquidity_guarded
     #[payable]
     pub fn nslp_add_liquidity(&mut self) -> u16 {
-        // TODO: Since this method doesn't guard the resulting liquidity, is it possible to put it
-        //    into a front-run/end-run sandwich to capitalize on the transaction?
+        self.nslp_add_liquidity_guarded(0.into(), None)
+    }
+
+    /// add liquidity - payable
+    /// guards against front-run/end-run sandwiches: panics if the attached NEAR buys less
+    /// than min_shares_out nslp shares or if the tx is included after deadline_block_height
+    #[payable]
+    pub fn nslp_add_liquidity_guarded(
+        &mut self,
+        min_shares_out: U128String,
+        deadline_block_height: Option<U64String>,
+    ) -> u16 {
+        self.assert_operation_not_paused(PAUSE_NSLP_ADD_LIQUIDITY);
+        self.assert_deadline_not_passed(deadline_block_height);
         let account_id = env::predecessor_account_id();
         let amount = self.internal_deposit(&account_id);
-        return self.internal_nslp_add_liquidity(&account_id, amount);
+        return self.internal_nslp_add_liquidity(&account_id, amount, min_shares_out.0);
     }
 
     /// remove liquidity from liquidity pool
+    /// no slippage protection, see nslp_remove_liquidity_guarded
     //#[payable]
     pub fn nslp_remove_liquidity(&mut self, amount: U128String) -> RemoveLiquidityResult {
+        self.nslp_remove_liquidity_guarded(amount, 0.into(), 0.into(), None)
+    }
+
+    /// remove liquidity from liquidity pool
+    /// panics if the resulting NEAR/stNEAR split is below min_near_out/min_st_near_out
+    /// or if the tx is included after deadline_block_height
+    pub fn nslp_remove_liquidity_guarded(
+        &mut self,
+        amount: U128String,
+        min_near_out: U128String,
+        min_st_near_out: U128String,
+        deadline_block_height: Option<U64String>,
+    ) -> RemoveLiquidityResult {
         self.assert_not_busy();
+        self.assert_operation_not_paused(PAUSE_NSLP_REMOVE_LIQUIDITY);
+        self.assert_deadline_not_passed(deadline_block_height);
         //assert_one_yocto();
 
         let account_id = env::predecessor_account_id();
@@ -808,9 +952,6 @@ impl MetaPool {
 
         assert!(nslp_shares_to_burn > 0);
 
-        //register removed liquidity to compute rewards correctly
-        acc.lp_meter.unstake(to_remove);
-
         //compute proportionals stNEAR/NEAR
         //1st: stNEAR how much stNEAR from the Liq-Pool represents the ratio: nslp_shares_to_burn relative to total nslp_shares
         let st_near_to_remove_from_pool = proportional(
@@ -825,16 +966,29 @@ impl MetaPool {
             "inconsistency NTR<STR+UTR"
         );
         let near_to_remove = to_remove - near_value_of_st_near;
+        assert!(
+            near_to_remove >= min_near_out.0,
+            "Price changed, you will get only {} NEAR, min_near_out {}",
+            near_to_remove,
+            min_near_out.0
+        );
+        assert!(
+            st_near_to_remove_from_pool >= min_st_near_out.0,
+            "Price changed, you will get only {} stNEAR, min_st_near_out {}",
+            st_near_to_remove_from_pool,
+            min_st_near_out.0
+        );
 
         //update user account
         //remove first from stNEAR in the pool, proportional to shares being burned
         //NOTE: To simplify user-operations, the LIQ.POOL DO NOT carry "unstaked". The NSLP self-balances only by internal-clearing on `deposit_and_stake`
         acc.available += near_to_remove;
-        acc.add_st_near(st_near_to_remove_from_pool, &self); //add stnear to user acc
+        acc.add_st_near(st_near_to_remove_from_pool, self); //add stnear to user acc
+        acc.nslp_cost_basis -= acc.nslp_cost_basis_of(nslp_shares_to_burn);
         acc.nslp_shares -= nslp_shares_to_burn; //shares this user burns
                                                 //update NSLP account
         nslp_account.available -= near_to_remove;
-        nslp_account.sub_st_near(st_near_to_remove_from_pool, &self); //remove stnear from the pool
+        nslp_account.sub_st_near(st_near_to_remove_from_pool, self); //remove stnear from the pool
         nslp_account.nslp_shares -= nslp_shares_to_burn; //burn from total nslp shares
 
         //simplify user-flow
@@ -846,12 +1000,38 @@ impl MetaPool {
         self.internal_update_account(&account_id, &acc);
         self.internal_save_nslp_account(&nslp_account);
 
-        event!(
-            r#"{{"event":"REM.L","account_id":"{}","near":"{}","stnear":"{}"}}"#,
-            account_id,
-            transfer_amount,
-            st_near_to_remove_from_pool
-        );
+        events::RemoveLiquidity {
+            account_id: &account_id,
+            near: transfer_amount.into(),
+            stnear: st_near_to_remove_from_pool.into(),
+            shares: nslp_shares_to_burn.into(),
+        }
+        .emit();
+        events::MtBurn {
+            owner_id: &account_id,
+            token_ids: vec![MT_TOKEN_NSLP],
+            amounts: vec![nslp_shares_to_burn.into()],
+            memo: None,
+        }
+        .emit();
+        // the NEAR paid by the NSLP (then sent to the user, see take_from_available)
+        events::MtTransfer {
+            old_owner_id: &NSLP_INTERNAL_ACCOUNT.into(),
+            new_owner_id: &account_id,
+            token_ids: vec![MT_TOKEN_NEAR],
+            amounts: vec![near_to_remove.into()],
+            memo: Some("nslp_remove_liquidity"),
+        }
+        .emit();
+        if st_near_to_remove_from_pool > 0 {
+            events::FtTransfer {
+                old_owner_id: &NSLP_INTERNAL_ACCOUNT.into(),
+                new_owner_id: &account_id,
+                amount: st_near_to_remove_from_pool.into(),
+                memo: Some("nslp_remove_liquidity"),
+            }
+            .emit();
+        }
 
         return RemoveLiquidityResult {
             near: transfer_amount.into(),
@@ -859,6 +1039,40 @@ impl MetaPool {
         };
     }
 
+    /// remove liquidity from liquidity pool, NEAR only
+    /// the NEAR part is transferred now, the stNEAR part is delayed-unstaked
+    /// for the LP (same as calling unstake) and can be withdrawn after unlock_epoch
+    pub fn nslp_remove_liquidity_near_only(
+        &mut self,
+        amount: U128String,
+        min_near_out: U128String,
+        min_st_near_out: U128String,
+        deadline_block_height: Option<U64String>,
+    ) -> RemoveLiquidityNearOnlyResult {
+        self.assert_not_lockup_account_calling();
+        self.assert_operation_not_paused(PAUSE_DELAYED_UNSTAKE);
+        let removed = self.nslp_remove_liquidity_guarded(
+            amount,
+            min_near_out,
+            min_st_near_out,
+            deadline_block_height,
+        );
+
+        let account_id = env::predecessor_account_id();
+        let mut acc = self.internal_get_account(&account_id);
+        let (unstaked, unlock_epoch) = if removed.st_near.0 > 0 {
+            self.internal_unstake_shares(&account_id, &mut acc, removed.st_near.0)
+        } else {
+            (0, acc.unstaked_requested_unlock_epoch)
+        };
+
+        RemoveLiquidityNearOnlyResult {
+            near: removed.near,
+            unstaked: unstaked.into(),
+            unlock_epoch: unlock_epoch.into(),
+        }
+    }
+
     //----------------------------------
     // Use part of the NSLP to stake. This is the inverse operation of nslp_try_internal_clearing
     // can be used by the operator to increase epoch_stake_orders
@@ -873,7 +1087,7 @@ impl MetaPool {
         let amount = near_amount.0;
         assert!(nslp_account.available > amount, "too much");
         assert!(
-            nslp_account.available - amount > self.nslp_liquidity_target,
+            nslp_account.available - amount > self.internal_nslp_liquidity_target(),
             "stake will leave NSLP below target"
         );
         // stake from nslp
@@ -895,31 +1109,102 @@ impl MetaPool {
         panic!("internal incentives have been deactivated. Use stNEAR in the ecosystem to get incentives");
     }
 
+    //---------------------------------------------------------------------------
+    /// Owner's method, 1st step of an upgrade.
+    /// Input is the new wasm code (raw bytes), only its sha256 is stored.
+    /// The code can be deployed with deploy_staged_upgrade after the upgrade timelock delay
+    pub fn stage_upgrade(&mut self) {
+        self.assert_owner_calling();
+        let code = env::input().expect("new code expected as input");
+        let mut code_hash = CryptoHash::default();
+        code_hash.copy_from_slice(&env::sha256(&code));
+        let staged_at = env::block_timestamp();
+        let deployable_at = staged_at
+            + self.timelock_delays.get(AdminActionClass::Upgrade) as u64 * 1_000_000_000;
+        events::Upgrade {
+            action: "stage",
+            code_hash: Some(code_hash.into()),
+            deployable_at: Some(deployable_at.into()),
+            by: &env::predecessor_account_id(),
+        }
+        .emit();
+        self.staged_upgrade = Some(StagedUpgrade {
+            code_hash,
+            staged_by: env::predecessor_account_id(),
+            staged_at,
+            deployable_at,
+        });
+    }
+
+    /// Guardian's method (also owner). Drops the staged upgrade
+    pub fn cancel_staged_upgrade(&mut self) {
+        self.assert_role(Role::Guardian);
+        assert!(self.staged_upgrade.is_some(), "no staged upgrade");
+        self.staged_upgrade = None;
+        events::Upgrade {
+            action: "cancel",
+            code_hash: None,
+            deployable_at: None,
+            by: &env::predecessor_account_id(),
+        }
+        .emit();
+    }
+
+    pub fn get_staged_upgrade(&self) -> Option<StagedUpgradeJSON> {
+        self.staged_upgrade.as_ref().map(|staged| StagedUpgradeJSON {
+            code_hash: staged.code_hash.into(),
+            staged_by: staged.staged_by.clone(),
+            staged_at: staged.staged_at.into(),
+            deployable_at: staged.deployable_at.into(),
+        })
+    }
+
     //---------------------------------------------------------------------------
     /// Sputnik DAO remote-upgrade receiver
     /// can be called by a remote-upgrade proposal
-    ///
+    /// same as deploy_staged_upgrade, the code must be staged first (see README, Upgrades)
     #[cfg(target_arch = "wasm32")]
-    pub fn upgrade(self) {
-        assert!(env::predecessor_account_id() == self.owner_account_id);
-        //input is code:<Vec<u8> on REGISTER 0
-        //log!("bytes.length {}", code.unwrap().len());
+    pub fn upgrade(&mut self) {
+        self.deploy_staged_upgrade();
+    }
+
+    /// Owner's method, 2nd step of an upgrade.
+    /// Input is the wasm code, it must match the staged hash and the upgrade timelock delay must have passed.
+    /// Deploys the code and calls `migrate` on the new code
+    #[cfg(target_arch = "wasm32")]
+    pub fn deploy_staged_upgrade(&mut self) {
+        self.assert_owner_calling();
         assert!(
             env::prepaid_gas() > 150 * TGAS,
             "set 200TGAS or more for this transaction"
         );
+        // not cleared here: the deploy+migrate receipt can still fail, `migrate` on the new code clears it
+        let staged = self.staged_upgrade.as_ref().expect("no staged upgrade, call stage_upgrade first");
+        let (code_hash, deployable_at) = (staged.code_hash, staged.deployable_at);
+        assert!(
+            env::block_timestamp() >= deployable_at,
+            "staged upgrade can not be deployed before {}",
+            deployable_at
+        );
+        let code = env::input().expect("new code expected as input");
+        assert!(
+            env::sha256(&code) == code_hash.to_vec(),
+            "code does not match the staged code hash"
+        );
+        self.assert_upgrade_invariants("before upgrade");
+        events::Upgrade {
+            action: "deploy",
+            code_hash: Some(code_hash.into()),
+            deployable_at: None,
+            by: &env::predecessor_account_id(),
+        }
+        .emit();
         const BLOCKCHAIN_INTERFACE_NOT_SET_ERR: &str = "Blockchain interface not set.";
         //after upgrade we call *pub fn migrate()* on the NEW CODE
         let current_id = env::current_account_id().into_bytes();
         let migrate_method_name = "migrate".as_bytes().to_vec();
         unsafe {
             BLOCKCHAIN_INTERFACE.with(|b| {
-                // Load input (new contract code) into register 0
-                b.borrow()
-                    .as_ref()
-                    .expect(BLOCKCHAIN_INTERFACE_NOT_SET_ERR)
-                    .input(0);
-
                 //prepare self-call promise
                 let promise_id = b
                     .borrow()
@@ -927,7 +1212,7 @@ impl MetaPool {
                     .expect(BLOCKCHAIN_INTERFACE_NOT_SET_ERR)
                     .promise_batch_create(current_id.len() as _, current_id.as_ptr() as _);
 
-                // 1st action, deploy/upgrade code (takes code from register 0)
+                // 1st action, deploy/upgrade code
                 // Note: this "promise preparation" CONSUMES an important amount of gas
                 // because at this point the WASM code is checked and "compiled"
                 // total gas cost formula is: (2 * 184765750000 + contract_size_in_bytes * (6812999 + 64572944) + 2 * 108059500000)
@@ -935,7 +1220,11 @@ impl MetaPool {
                 b.borrow()
                     .as_ref()
                     .expect(BLOCKCHAIN_INTERFACE_NOT_SET_ERR)
-                    .promise_batch_action_deploy_contract(promise_id, u64::MAX as _, 0);
+                    .promise_batch_action_deploy_contract(
+                        promise_id,
+                        code.len() as _,
+                        code.as_ptr() as _,
+                    );
 
                 // 2nd action, schedule a call to "migrate()".
                 // Will execute on the **new code**
@@ -959,3 +1248,69 @@ impl MetaPool {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    #[test]
+    fn test_buy_stnear_from_nslp() {
+        let mut contract = contract_with_balances();
+        let quote = contract.get_buy_stnear_quote(ntoy(10).into());
+        assert_eq!(quote.near_to_nslp.0, ntoy(10));
+        assert_eq!(quote.near_available.0, 0);
+        // bought below the minting price
+        assert!(quote.buy_st_near.0 > quote.mint_st_near.0);
+
+        set_context(&alice(), ntoy(10));
+        let result = contract.buy_stnear(quote.buy_st_near);
+        assert_eq!(result.st_near, quote.buy_st_near);
+        assert_eq!(result.from_nslp, quote.from_nslp);
+        let alice_account = contract.internal_get_account(&alice());
+        assert_eq!(alice_account.stake_shares, ntoy(20) + result.st_near.0);
+        assert_eq!(alice_account.available, ntoy(10));
+        let nslp_account = contract.internal_get_nslp_account();
+        assert_eq!(nslp_account.available, ntoy(40));
+        assert_eq!(nslp_account.stake_shares, ntoy(100) - result.from_nslp.0);
+        // no stNEAR minted
+        assert_eq!(contract.total_stake_shares, ntoy(120));
+    }
+
+    #[test]
+    fn test_buy_stnear_mints_the_rest() {
+        let mut contract = contract_with_balances();
+        let quote = contract.get_buy_stnear_quote(ntoy(150).into());
+        assert_eq!(quote.from_nslp.0, ntoy(100));
+
+        set_context(&alice(), ntoy(150));
+        let result = contract.buy_stnear(quote.buy_st_near);
+        assert_eq!(result.st_near, quote.buy_st_near);
+        assert_eq!(result.near_available.0, 0);
+        let nslp_account = contract.internal_get_nslp_account();
+        assert_eq!(nslp_account.stake_shares, 0);
+        assert_eq!(nslp_account.available, ntoy(30) + quote.near_to_nslp.0);
+        let minted = ntoy(150) - quote.near_to_nslp.0;
+        assert_eq!(contract.total_stake_shares, ntoy(120) + minted);
+        assert_eq!(contract.epoch_stake_orders, minted);
+        assert_eq!(contract.internal_get_account(&alice()).available, ntoy(10));
+    }
+
+    #[test]
+    #[should_panic(expected = "Price changed")]
+    fn test_buy_stnear_slippage() {
+        let mut contract = contract_with_balances();
+        let quote = contract.get_buy_stnear_quote(ntoy(10).into());
+        set_context(&alice(), ntoy(10));
+        contract.buy_stnear((quote.buy_st_near.0 + 1).into());
+    }
+
+    #[test]
+    #[should_panic(expected = "operation paused")]
+    fn test_buy_stnear_paused() {
+        let mut contract = contract_with_balances();
+        contract.paused_operations = PAUSE_BUY_STNEAR;
+        set_context(&alice(), ntoy(10));
+        contract.buy_stnear(0.into());
+    }
+}
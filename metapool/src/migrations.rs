@@ -175,23 +175,227 @@ pub struct OldMetaPool {
 use crate::MetaPool;
 use crate::MetaPoolContract;
 
+//---------------------------------------------------
+//  STATE VERSIONS & MIGRATION STEPS
+//---------------------------------------------------
+// v1: legacy state, no state_version field (OldMetaPool)
+// v2: lockup registry, pause switches, roles, timelock, two-step transfers, staged upgrade,
+//     removes the deprecated META incentives state (MetaPool & Account fields)
+//
+// To change the state in a new release:
+// - increment STATE_VERSION (types.rs)
+// - append a MigrationStep to MIGRATION_STEPS, from_version = STATE_VERSION-1
+// - if the MetaPool layout changed, keep the previous layout as OldMetaPool and convert it in `load_previous_layout`,
+//   selected by the stored state version (STATE_VERSION_KEY)
+// - if the Account layout changed, increment ACCOUNT_LAYOUT_VERSION (types.rs), add the new layout
+//   to `impl BorshDeserialize for Account` and to `account_layout_state_version`.
+//   Old account layouts can be removed once get_state_version().accounts_version reaches the new version
+
+/// a migration step from `from_version` to `from_version+1`
+pub struct MigrationStep {
+    pub from_version: u16,
+    /// applied to the main state in `migrate`
+    pub state: fn(&mut MetaPool),
+    /// applied to each account read from an older layout, see upgrade_account
+    pub account: Option<fn(&mut Account)>,
+}
+
+/// registry of migration steps, applied in sequence by `migrate`
+pub const MIGRATION_STEPS: &[MigrationStep] = &[MigrationStep {
+    from_version: 1,
+    state: migrate_state_v1_to_v2,
+    account: None,
+}];
+
+fn get_migration_step(from_version: u16) -> &'static MigrationStep {
+    match MIGRATION_STEPS.iter().find(|x| x.from_version == from_version) {
+        Some(step) => step,
+        None => panic!("no migration step from state version {}", from_version),
+    }
+}
+
+/// state version of the code that wrote an account layout, see ACCOUNT_LAYOUT_VERSION
+fn account_layout_state_version(layout_version: u16) -> u16 {
+    match layout_version {
+        1 => 1,
+        2..=4 => 2,
+        _ => panic!("unknown account layout version {}", layout_version),
+    }
+}
+
+/// applies the account migration steps from the state version that wrote the account up to STATE_VERSION.
+/// Called when the account is read (lazy migration)
+pub(crate) fn upgrade_account(account: &mut Account) {
+    for from_version in account_layout_state_version(account.stored_version)..STATE_VERSION {
+        if let Some(account_step) = get_migration_step(from_version).account {
+            account_step(account);
+        }
+    }
+}
+
+/// v1->v2: set defaults for the features added in v2
+fn migrate_state_v1_to_v2(state: &mut MetaPool) {
+    state.lockup_contracts = default_lockup_contracts();
+    state.lockup_account_suffixes = default_lockup_account_suffixes();
+    state.timelock_delays = TimelockDelays::default();
+}
+
+/// storage key of the state version, written next to STATE so the stored layout
+/// is known before parsing it. Absent in v1 (legacy state)
+const STATE_VERSION_KEY: &[u8] = b"STATE_VERSION";
+
+/// records the version of the layout written to STATE, see load_previous_layout
+pub(crate) fn write_stored_state_version(version: u16) {
+    env::storage_write(STATE_VERSION_KEY, &version.try_to_vec().unwrap());
+}
+
+fn read_stored_state_version() -> u16 {
+    match env::storage_read(STATE_VERSION_KEY) {
+        Some(raw) => u16::try_from_slice(&raw).expect("invalid stored state version"),
+        None => 1,
+    }
+}
+
+/// the state stored by the previous code, converted to the current layout
+/// without changing values (values are changed by the MigrationSteps).
+/// The layout is selected by the stored state version.
+/// Returns also the stored length
+fn load_previous_layout() -> (MetaPool, usize) {
+    let raw_state = env::storage_read(b"STATE").expect("Old state doesn't exist");
+    let stored_version = read_stored_state_version();
+    if stored_version == STATE_VERSION {
+        // already in the current layout (only values changed)
+        let current = MetaPool::try_from_slice(&raw_state).expect("invalid state for the stored version");
+        assert_eq!(current.state_version, stored_version, "inconsistent stored state version");
+        return (current, raw_state.len());
+    }
+    assert_eq!(stored_version, 1, "no layout for stored state version {}", stored_version);
+    let old = OldMetaPool::try_from_slice(&raw_state).expect("invalid v1 state");
+
+    // values carried as-is, `migrate` checks the state is at rest
+    let state = MetaPool {
+        owner_account_id: old.owner_account_id,
+        contract_busy: old.contract_busy,
+        staking_paused: old.staking_paused,
+        contract_account_balance: old.contract_account_balance,
+        retrieved_for_unstake_claims: old.retrieved_for_unstake_claims,
+        total_available: old.total_available,
+
+        //-- ORDERS
+        epoch_stake_orders: old.epoch_stake_orders,
+        epoch_unstake_orders: old.epoch_unstake_orders,
+        epoch_last_clearing: old.epoch_last_clearing,
+
+        total_for_staking: old.total_for_staking,
+        total_actually_staked: old.total_actually_staked,
+        total_stake_shares: old.total_stake_shares,
+        total_unstaked_and_waiting: old.total_unstaked_and_waiting,
+
+        total_unstake_claims: old.total_unstake_claims,
+
+        accumulated_staked_rewards: old.accumulated_staked_rewards,
+
+        accounts: old.accounts,
+
+        staking_pools: old.staking_pools,
+
+        loan_requests: old.loan_requests,
+
+        nslp_liquidity_target: old.nslp_liquidity_target,
+        nslp_relative_target: None,
+        nslp_max_discount_basis_points: old.nslp_max_discount_basis_points,
+        nslp_min_discount_basis_points: old.nslp_min_discount_basis_points,
+        nslp_fee_curve: NslpFeeCurve::Linear,
+        nslp_buy_discount_basis_points: DEFAULT_NSLP_BUY_DISCOUNT_BASIS_POINTS,
+        nslp_total_fees_retained: 0,
+        nslp_history: LookupMap::new(b"H".to_vec()),
+        nslp_history_count: 0,
+
+        operator_account_id: old.operator_account_id,
+        treasury_account_id: old.treasury_account_id,
+
+        // v1 hard-coded the developers cut
+        fee_beneficiaries: vec![
+            FeeBeneficiary {
+                recipient: FeeRecipient::Operator,
+                rewards_fee_bp: old.operator_rewards_fee_basis_points,
+                swap_cut_bp: old.operator_swap_cut_basis_points,
+            },
+            FeeBeneficiary {
+                recipient: FeeRecipient::Treasury,
+                rewards_fee_bp: 0,
+                swap_cut_bp: old.treasury_swap_cut_basis_points,
+            },
+            FeeBeneficiary {
+                recipient: FeeRecipient::Account(DEVELOPERS_ACCOUNT_ID.into()),
+                rewards_fee_bp: DEVELOPERS_REWARDS_FEE_BASIS_POINTS,
+                swap_cut_bp: DEVELOPERS_SWAP_CUT_BASIS_POINTS,
+            },
+        ],
+        fee_accruals: LookupMap::new(b"F".to_vec()),
+        fee_auto_unstake_last_epoch: 0,
+
+        // Configurable info for [NEP-129](https://github.com/nearprotocol/NEPs/pull/129)
+        web_app_url: old.web_app_url,
+        auditor_account_id: old.auditor_account_id,
+
+        min_deposit_amount: old.min_deposit_amount,
+
+        unstaked_for_rebalance: old.unstaked_for_rebalance,
+        unstake_for_rebalance_cap_bp: old.unstake_for_rebalance_cap_bp,
+
+        lockup_contracts: Vec::new(),
+        lockup_account_suffixes: Vec::new(),
+
+        paused_operations: 0,
+        roles: UnorderedMap::new(b"R".to_vec()),
+
+        timelock_delays: TimelockDelays::none(),
+        pending_admin_actions: Vec::new(),
+        next_admin_action_id: 1,
+
+        pending_owner: None,
+        pending_operator: None,
+        pending_treasury: None,
+
+        staged_upgrade: None,
+
+        gas_rebate_split: GasRebateSplit::default(),
+        gas_rebate_last_epoch: 0,
+        total_gas_rebate_routed: 0,
+
+        reserves_snapshot: None,
+
+        state_version: 1,
+        accounts_version: 1,
+        accounts_migration_index: 0,
+    };
+    (state, raw_state.len())
+}
+
+impl MetaPool {
+    /// UnorderedMap::remove moves the last account into the removed slot, which would
+    /// make the index-based accounts sweep skip it. Accounts can not be removed until the sweep is done
+    pub(crate) fn assert_accounts_not_migrating(&self) {
+        assert!(
+            self.accounts_version >= ACCOUNT_LAYOUT_VERSION,
+            "accounts migration in progress, try again after migrate_accounts completes"
+        );
+    }
+}
+
 #[near_bindgen]
 impl MetaPool {
     //-----------------
     //-- migration called after code upgrade
-    ///  For next version upgrades, change this function.
     //-- executed after upgrade to NEW CODE
     //-----------------
     /// This fn WILL be called by this contract from `pub fn upgrade` (started from DAO)
-    /// Originally a **NOOP implementation. KEEP IT if you haven't changed contract state.**
-    /// If you have changed state, you need to implement migration from old state (keep the old struct with different name to deserialize it first).
+    /// Loads the stored state and applies MIGRATION_STEPS from its state_version up to STATE_VERSION.
+    /// Accounts are migrated on first touch or by migrate_accounts
     ///
     #[init(ignore_state)] //do not auto-load state before this function
     pub fn migrate() -> Self {
-        // read state with OLD struct
-        // uncomment when state migration is required on upgrade
-        let old: OldMetaPool = env::state_read().expect("Old state doesn't exist");
-
         // can only be called by this same contract (it's called from fn upgrade())
         if !env::current_account_id().ends_with(".testnet") {
             assert_eq!(
@@ -201,68 +405,271 @@ impl MetaPool {
             );
         }
 
-        // Create the new contract state using the data from the old contract state.
-        // returns this struct that gets stored as contract state
-        return Self {
-            owner_account_id: old.owner_account_id,
-            contract_busy: false,
-            staking_paused: old.staking_paused,
-            contract_account_balance: old.contract_account_balance,
-            retrieved_for_unstake_claims: old.retrieved_for_unstake_claims,
-            total_available: old.total_available,
+        let (mut state, stored_len) = load_previous_layout();
+        state.assert_upgrade_invariants("before migration");
+        assert!(
+            state.state_version <= STATE_VERSION,
+            "stored state version {} is newer than code state version {}",
+            state.state_version,
+            STATE_VERSION
+        );
+
+        while state.state_version < STATE_VERSION {
+            let step = get_migration_step(state.state_version);
+            (step.state)(&mut state);
+            state.state_version += 1;
+        }
+        // accounts stored in an older layout than this code's, restart the sweep:
+        // accounts before the index may be in the layout of a previous sweep
+        if state.accounts_version < ACCOUNT_LAYOUT_VERSION {
+            state.accounts_migration_index = 0;
+        }
 
-            //-- ORDERS
-            epoch_stake_orders: old.epoch_stake_orders,
-            epoch_unstake_orders: old.epoch_unstake_orders,
-            epoch_last_clearing: old.epoch_last_clearing,
+        // the staged code is deployed now
+        state.staged_upgrade = None;
 
-            total_for_staking: old.total_for_staking,
-            total_actually_staked: old.total_actually_staked,
-            total_stake_shares: old.total_stake_shares,
-            total_meta: old.total_meta,
-            total_unstaked_and_waiting: old.total_unstaked_and_waiting,
+        state.assert_upgrade_invariants("after migration");
+        write_stored_state_version(state.state_version);
+        events::Migrate {
+            state_version: state.state_version,
+            accounts_version: state.accounts_version,
+            stored_bytes: Some((stored_len as u64).into()),
+            new_bytes: Some((state.try_to_vec().unwrap().len() as u64).into()),
+        }
+        .emit();
+        return state;
+    }
 
-            total_unstake_claims: old.total_unstake_claims,
+    /// Operator's method. Rewrites in the current layout the accounts in [from_index, from_index+limit)
+    /// stored in an older layout. Sweeping all the accounts in order sets accounts_version to ACCOUNT_LAYOUT_VERSION.
+    /// Accounts can not be removed during the sweep (see assert_accounts_not_migrating), so indexes are stable.
+    /// Returns the index to continue from
+    pub fn migrate_accounts(&mut self, from_index: u64, limit: u32) -> AccountsMigrationJSON {
+        self.assert_operator_or_owner();
+        let accounts_count = self.accounts.len();
+        let to_index = std::cmp::min(from_index + limit as u64, accounts_count);
+        let mut migrated: u32 = 0;
+        let storage_before = env::storage_usage();
+        for inx in from_index..to_index {
+            let account_id = self.accounts.keys_as_vector().get(inx).unwrap();
+            // upgraded on read
+            let account = self.accounts.get(&account_id).unwrap();
+            if account.needs_migration() {
+                self.accounts.insert(&account_id, &account);
+                migrated += 1;
+            }
+        }
+        // progress is tracked only for a contiguous sweep
+        if from_index <= self.accounts_migration_index && to_index > self.accounts_migration_index {
+            self.accounts_migration_index = to_index;
+        }
+        if self.accounts_migration_index >= accounts_count {
+            self.accounts_version = ACCOUNT_LAYOUT_VERSION;
+        }
+        let storage_reclaimed = storage_before.saturating_sub(env::storage_usage());
+        events::MigrateAccounts {
+            from_index: from_index.into(),
+            to_index: to_index.into(),
+            migrated,
+            storage_reclaimed: storage_reclaimed.into(),
+            accounts_version: self.accounts_version,
+        }
+        .emit();
+        AccountsMigrationJSON {
+            next_index: to_index.into(),
+            migrated,
+            storage_reclaimed: storage_reclaimed.into(),
+            accounts_migration_index: self.accounts_migration_index.into(),
+            accounts_version: self.accounts_version,
+        }
+    }
 
-            accumulated_staked_rewards: old.accumulated_staked_rewards,
+    pub fn get_state_version(&self) -> StateVersionJSON {
+        StateVersionJSON {
+            state_version: self.state_version,
+            code_state_version: STATE_VERSION,
+            accounts_version: self.accounts_version,
+            code_accounts_version: ACCOUNT_LAYOUT_VERSION,
+            accounts_migration_index: self.accounts_migration_index.into(),
+            accounts_count: self.accounts.len().into(),
+        }
+    }
+}
 
-            accounts: old.accounts,
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
 
-            staking_pools: old.staking_pools,
+    fn v1_state() -> OldMetaPool {
+        OldMetaPool {
+            owner_account_id: owner(),
+            contract_busy: false,
+            staking_paused: false,
+            contract_account_balance: 0,
+            retrieved_for_unstake_claims: 0,
+            total_available: 0,
+            epoch_stake_orders: 0,
+            epoch_unstake_orders: 0,
+            epoch_last_clearing: 0,
+            total_for_staking: 0,
+            total_actually_staked: 0,
+            total_stake_shares: 0,
+            total_meta: 0,
+            total_unstaked_and_waiting: 0,
+            total_unstake_claims: 0,
+            accumulated_staked_rewards: 0,
+            accounts: UnorderedMap::new(b"A".to_vec()),
+            staking_pools: Vec::new(),
+            loan_requests: LookupMap::new(b"L".to_vec()),
+            nslp_liquidity_target: 10_000 * NEAR,
+            nslp_max_discount_basis_points: 180,
+            nslp_min_discount_basis_points: 25,
+            staker_meta_mult_pct: 5000,
+            stnear_sell_meta_mult_pct: 50,
+            lp_provider_meta_mult_pct: 200,
+            min_deposit_amount: NEAR,
+            operator_account_id: operator(),
+            operator_rewards_fee_basis_points: 50,
+            operator_swap_cut_basis_points: 300,
+            treasury_account_id: treasury(),
+            treasury_swap_cut_basis_points: 2500,
+            web_app_url: None,
+            auditor_account_id: None,
+            meta_token_account_id: "meta-token.near".to_string(),
+            est_meta_rewards_stakers: 0,
+            est_meta_rewards_lu: 0,
+            est_meta_rewards_lp: 0,
+            max_meta_rewards_stakers: 0,
+            max_meta_rewards_lu: 0,
+            max_meta_rewards_lp: 0,
+            unstake_for_rebalance_cap_bp: 100,
+            unstaked_for_rebalance: 0,
+        }
+    }
+
+    /// stores a v1 state (no stored state version)
+    fn store_v1_state() {
+        set_context(&contract_account(), 0);
+        env::state_write(&v1_state());
+        env::storage_remove(STATE_VERSION_KEY);
+    }
 
-            loan_requests: old.loan_requests,
+    #[test]
+    fn test_new_records_state_version() {
+        new_contract();
+        assert_eq!(read_stored_state_version(), STATE_VERSION);
+    }
+
+    #[test]
+    fn test_migrate_from_v1() {
+        store_v1_state();
+        let state = MetaPool::migrate();
+        assert_eq!(state.state_version, STATE_VERSION);
+        assert_eq!(read_stored_state_version(), STATE_VERSION);
+        assert_eq!(state.owner_account_id, owner());
+        assert_eq!(state.nslp_liquidity_target, 10_000 * NEAR);
+        assert_eq!(state.lockup_contracts, default_lockup_contracts());
+        // developers cut preserved as a fee beneficiary
+        assert_eq!(state.fee_beneficiaries.len(), 3);
+        assert_eq!(state.get_reward_fee_bp(), 50 + DEVELOPERS_REWARDS_FEE_BASIS_POINTS);
+        // accounts stored by v1 must be swept
+        assert_eq!(state.accounts_version, 1);
+    }
 
-            nslp_liquidity_target: old.nslp_liquidity_target,
-            nslp_max_discount_basis_points: old.nslp_max_discount_basis_points,
-            nslp_min_discount_basis_points: old.nslp_min_discount_basis_points,
+    #[test]
+    #[should_panic(expected = "before migration: contract is busy")]
+    fn test_migrate_from_busy_v1() {
+        set_context(&contract_account(), 0);
+        let mut old = v1_state();
+        old.contract_busy = true;
+        env::state_write(&old);
+        env::storage_remove(STATE_VERSION_KEY);
+        MetaPool::migrate();
+    }
 
-            staker_meta_mult_pct: old.staker_meta_mult_pct,
-            stnear_sell_meta_mult_pct: old.stnear_sell_meta_mult_pct,
-            lp_provider_meta_mult_pct: old.lp_provider_meta_mult_pct,
+    #[test]
+    fn test_migrate_current_layout() {
+        let contract = new_contract();
+        env::state_write(&contract);
+        set_context(&contract_account(), 0);
+        let state = MetaPool::migrate();
+        assert_eq!(state.state_version, STATE_VERSION);
+        assert_eq!(state.accounts_version, ACCOUNT_LAYOUT_VERSION);
+        assert_eq!(state.owner_account_id, owner());
+    }
 
-            operator_account_id: old.operator_account_id,
-            operator_rewards_fee_basis_points: old.operator_rewards_fee_basis_points,
-            operator_swap_cut_basis_points: old.operator_swap_cut_basis_points,
+    #[test]
+    fn test_migrate_clears_staged_upgrade() {
+        let mut contract = new_contract();
+        contract.staged_upgrade = Some(StagedUpgrade {
+            code_hash: CryptoHash::default(),
+            staged_by: owner(),
+            staged_at: 0,
+            deployable_at: 0,
+        });
+        env::state_write(&contract);
+        set_context(&contract_account(), 0);
+        let state = MetaPool::migrate();
+        assert!(state.staged_upgrade.is_none());
+    }
 
-            treasury_account_id: old.treasury_account_id,
-            treasury_swap_cut_basis_points: old.treasury_swap_cut_basis_points,
+    #[test]
+    fn test_migrate_restarts_accounts_sweep() {
+        let mut contract = new_contract();
+        // swept up to index 5 for an older account layout
+        contract.accounts_version = ACCOUNT_LAYOUT_VERSION - 1;
+        contract.accounts_migration_index = 5;
+        env::state_write(&contract);
+        set_context(&contract_account(), 0);
+        let state = MetaPool::migrate();
+        assert_eq!(state.accounts_migration_index, 0);
+        assert_eq!(state.accounts_version, ACCOUNT_LAYOUT_VERSION - 1);
+        assert_eq!(state.get_state_version().code_accounts_version, ACCOUNT_LAYOUT_VERSION);
+    }
 
-            // Configurable info for [NEP-129](https://github.com/nearprotocol/NEPs/pull/129)
-            web_app_url: old.web_app_url,
-            auditor_account_id: old.auditor_account_id,
+    #[test]
+    #[should_panic(expected = "no layout for stored state version")]
+    fn test_migrate_unknown_version() {
+        store_v1_state();
+        write_stored_state_version(STATE_VERSION + 1);
+        MetaPool::migrate();
+    }
 
-            meta_token_account_id: old.meta_token_account_id,
-            min_deposit_amount: old.min_deposit_amount,
+    /// v1 state migrated, with two accounts pending the sweep
+    fn migrated_with_accounts() -> MetaPool {
+        store_v1_state();
+        let mut state = MetaPool::migrate();
+        state.accounts.insert(&alice(), &Account::default());
+        state.accounts.insert(&bob(), &Account::default());
+        state
+    }
 
-            est_meta_rewards_stakers: old.est_meta_rewards_stakers,
-            est_meta_rewards_lu: old.est_meta_rewards_lu,
-            est_meta_rewards_lp: old.est_meta_rewards_lp,
-            max_meta_rewards_stakers: old.max_meta_rewards_stakers,
-            max_meta_rewards_lu: old.max_meta_rewards_lu,
-            max_meta_rewards_lp: old.max_meta_rewards_lp,
+    #[test]
+    #[should_panic(expected = "accounts migration in progress")]
+    fn test_unregister_frozen_during_accounts_sweep() {
+        let mut state = migrated_with_accounts();
+        set_context(&bob(), 1);
+        state.storage_unregister(None);
+    }
 
-            unstaked_for_rebalance: old.unstaked_for_rebalance, 
-            unstake_for_rebalance_cap_bp: old.unstake_for_rebalance_cap_bp,
-        };
+    #[test]
+    fn test_accounts_sweep() {
+        let mut state = migrated_with_accounts();
+
+        set_context(&operator(), 0);
+        let result = state.migrate_accounts(0, 1);
+        assert_eq!(result.next_index.0, 1);
+        assert_eq!(state.accounts_version, 1);
+        // not contiguous, progress not recorded
+        state.migrate_accounts(2, 10);
+        assert_eq!(state.accounts_migration_index, 1);
+        let result = state.migrate_accounts(1, 10);
+        assert_eq!(result.next_index.0, 2);
+        assert_eq!(state.accounts_version, ACCOUNT_LAYOUT_VERSION);
+
+        set_context(&bob(), 1);
+        assert!(state.storage_unregister(None));
+        assert_eq!(state.accounts.len(), 1);
     }
 }
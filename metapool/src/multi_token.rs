@@ -0,0 +1,645 @@
+//! NEP-245 (Multi Token) interface
+//! <https://github.com/near/NEPs/blob/master/neps/nep-0245.md>
+//!
+//! Exposes the internal balances of an `Account` as token ids:
+//! - "near": available NEAR (`Account.available`)
+//! - "stnear": stNEAR (`Account.stake_shares`), same balance as the NEP-141 token
+//! - "nslp": NSLP shares, so LP positions can be deposited into farms and lending markets
+//!
+//! Every balance change of the three tokens logs a NEP-245 event (mt_mint, mt_transfer, mt_burn),
+//! stNEAR changes log the NEP-141 event too, see events.rs.
+//!
+//! Approvals are not supported.
+
+use near_sdk::json_types::{ValidAccountId, U128};
+use near_sdk::serde::Serialize;
+use near_sdk::{env, ext_contract, near_bindgen, AccountId, Balance, Gas, PromiseOrValue, PromiseResult};
+
+use crate::*;
+
+#[ext_contract(ext_mt_receiver)]
+pub trait MultiTokenReceiver {
+    fn mt_on_transfer(
+        &mut self,
+        sender_id: AccountId,
+        previous_owner_ids: Vec<AccountId>,
+        token_ids: Vec<String>,
+        amounts: Vec<U128>,
+        msg: String,
+    ) -> PromiseOrValue<Vec<U128>>;
+}
+
+#[ext_contract(ext_mt_self)]
+trait MultiTokenResolver {
+    fn mt_resolve_transfer(
+        &mut self,
+        previous_owner_ids: Vec<AccountId>,
+        receiver_id: AccountId,
+        token_ids: Vec<String>,
+        amounts: Vec<U128>,
+    ) -> Vec<U128>;
+}
+
+const GAS_FOR_MT_TRANSFER_CALL: Gas = 30_000_000_000_000;
+const GAS_FOR_MT_RESOLVE_TRANSFER: Gas = 15_000_000_000_000;
+const FIVE_TGAS: Gas = 5_000_000_000_000;
+const ONE_TGAS: Gas = 1_000_000_000_000;
+
+const NO_DEPOSIT: Balance = 0;
+
+pub const MT_METADATA_SPEC: &str = "mt-1.0.0";
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct MtContractMetadata {
+    pub spec: String,
+    pub name: String,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct MtBaseTokenMetadata {
+    pub name: String,
+    pub id: String,
+    pub symbol: Option<String>,
+    pub icon: Option<String>,
+    pub decimals: Option<String>,
+    pub base_uri: Option<String>,
+    pub reference: Option<String>,
+    pub copies: Option<u64>,
+    pub reference_hash: Option<String>,
+}
+
+fn mt_base_token_metadata(token_id: &str) -> Option<MtBaseTokenMetadata> {
+    let (name, symbol) = match token_id {
+        MT_TOKEN_NEAR => ("Meta Pool available NEAR", "NEAR"),
+        MT_TOKEN_STNEAR => ("Staked NEAR", "STNEAR"),
+        MT_TOKEN_NSLP => ("Meta Pool NEAR/stNEAR LP share", "NSLP"),
+        _ => return None,
+    };
+    Some(MtBaseTokenMetadata {
+        name: name.into(),
+        id: token_id.into(),
+        symbol: Some(symbol.into()),
+        icon: None,
+        decimals: Some("24".into()),
+        base_uri: None,
+        reference: Some("https://metapool.app".into()),
+        copies: None,
+        reference_hash: None,
+    })
+}
+
+#[near_bindgen]
+impl MetaPool {
+    /// Transfer `amount` of `token_id` from the caller to `receiver_id`.
+    /// receiver_id must be registered
+    #[payable]
+    pub fn mt_transfer(
+        &mut self,
+        receiver_id: ValidAccountId,
+        token_id: String,
+        amount: U128,
+        approval: Option<(AccountId, u64)>,
+        memo: Option<String>,
+    ) {
+        assert_one_yocto();
+        assert!(approval.is_none(), "approvals are not supported");
+        self.assert_mt_transfer_allowed(&token_id);
+        self.internal_mt_transfer(
+            &env::predecessor_account_id(),
+            &receiver_id.into(),
+            &token_id,
+            amount.0,
+            memo.as_deref(),
+        );
+    }
+
+    /// Transfer `amounts` of `token_ids` from the caller to `receiver_id`.
+    /// receiver_id must be registered
+    #[payable]
+    pub fn mt_batch_transfer(
+        &mut self,
+        receiver_id: ValidAccountId,
+        token_ids: Vec<String>,
+        amounts: Vec<U128>,
+        approvals: Option<Vec<Option<(AccountId, u64)>>>,
+        memo: Option<String>,
+    ) {
+        assert_one_yocto();
+        self.internal_mt_batch_transfer(
+            &env::predecessor_account_id(),
+            &receiver_id.into(),
+            &token_ids,
+            &amounts,
+            approvals,
+            memo.as_deref(),
+        );
+    }
+
+    /// Transfer `amount` of `token_id` to a contract at `receiver_id`, then call
+    /// `mt_on_transfer` on it. Unused amounts are refunded by mt_resolve_transfer
+    #[payable]
+    pub fn mt_transfer_call(
+        &mut self,
+        receiver_id: ValidAccountId,
+        token_id: String,
+        amount: U128,
+        approval: Option<(AccountId, u64)>,
+        memo: Option<String>,
+        msg: String,
+    ) -> PromiseOrValue<Vec<U128>> {
+        assert_one_yocto();
+        self.internal_mt_batch_transfer_call(
+            receiver_id.into(),
+            vec![token_id],
+            vec![amount],
+            Some(vec![approval]),
+            memo,
+            msg,
+        )
+    }
+
+    /// Transfer `amounts` of `token_ids` to a contract at `receiver_id`, then call
+    /// `mt_on_transfer` on it. Unused amounts are refunded by mt_resolve_transfer
+    #[payable]
+    pub fn mt_batch_transfer_call(
+        &mut self,
+        receiver_id: ValidAccountId,
+        token_ids: Vec<String>,
+        amounts: Vec<U128>,
+        approvals: Option<Vec<Option<(AccountId, u64)>>>,
+        memo: Option<String>,
+        msg: String,
+    ) -> PromiseOrValue<Vec<U128>> {
+        assert_one_yocto();
+        self.internal_mt_batch_transfer_call(
+            receiver_id.into(),
+            token_ids,
+            amounts,
+            approvals,
+            memo,
+            msg,
+        )
+    }
+
+    /// executed after mt_transfer_call, refunds the unused amounts.
+    /// Returns the amounts finally transferred
+    #[private]
+    pub fn mt_resolve_transfer(
+        &mut self,
+        previous_owner_ids: Vec<AccountId>,
+        receiver_id: AccountId,
+        token_ids: Vec<String>,
+        amounts: Vec<U128>,
+    ) -> Vec<U128> {
+        // Get the unused amounts from the `mt_on_transfer` call result.
+        let unused_amounts: Vec<u128> = match env::promise_result(0) {
+            PromiseResult::NotReady => unreachable!(),
+            PromiseResult::Successful(value) => {
+                match near_sdk::serde_json::from_slice::<Vec<U128>>(&value) {
+                    Ok(unused) if unused.len() == amounts.len() => amounts
+                        .iter()
+                        .zip(unused.iter())
+                        .map(|(amount, unused)| std::cmp::min(amount.0, unused.0))
+                        .collect(),
+                    _ => amounts.iter().map(|x| x.0).collect(),
+                }
+            }
+            PromiseResult::Failed => amounts.iter().map(|x| x.0).collect(),
+        };
+
+        let mut used_amounts = Vec::with_capacity(amounts.len());
+        for inx in 0..token_ids.len() {
+            let refunded = self.internal_mt_refund(
+                &receiver_id,
+                &previous_owner_ids[inx],
+                &token_ids[inx],
+                unused_amounts[inx],
+            );
+            used_amounts.push((amounts[inx].0 - refunded).into());
+        }
+        used_amounts
+    }
+
+    /// balance of `token_id` for `account_id`
+    pub fn mt_balance_of(&self, account_id: AccountId, token_id: String) -> U128 {
+        self.internal_mt_balance_of(&account_id, &token_id).into()
+    }
+
+    pub fn mt_batch_balance_of(&self, account_id: AccountId, token_ids: Vec<String>) -> Vec<U128> {
+        token_ids
+            .iter()
+            .map(|token_id| self.internal_mt_balance_of(&account_id, token_id).into())
+            .collect()
+    }
+
+    /// total supply of `token_id`
+    pub fn mt_supply(&self, token_id: String) -> Option<U128> {
+        self.internal_mt_supply(&token_id).map(|supply| supply.into())
+    }
+
+    pub fn mt_batch_supply(&self, token_ids: Vec<String>) -> Vec<Option<U128>> {
+        token_ids
+            .iter()
+            .map(|token_id| self.internal_mt_supply(token_id).map(|supply| supply.into()))
+            .collect()
+    }
+
+    pub fn mt_metadata_contract(&self) -> MtContractMetadata {
+        MtContractMetadata {
+            spec: MT_METADATA_SPEC.into(),
+            name: "Meta Pool".into(),
+        }
+    }
+
+    pub fn mt_metadata_base_by_token_id(&self, token_ids: Vec<String>) -> Vec<Option<MtBaseTokenMetadata>> {
+        token_ids
+            .iter()
+            .map(|token_id| mt_base_token_metadata(token_id))
+            .collect()
+    }
+
+    /// NEAR value of one token (1e24 units) of `token_id`
+    pub fn get_mt_token_price(&self, token_id: String) -> U128 {
+        match token_id.as_str() {
+            MT_TOKEN_NEAR => ONE_E24.into(),
+            MT_TOKEN_STNEAR => self.amount_from_stake_shares(ONE_E24).into(),
+            MT_TOKEN_NSLP => self
+                .amount_from_nslp_shares(ONE_E24, &self.internal_get_nslp_account())
+                .into(),
+            _ => panic!("unknown token_id {}", token_id),
+        }
+    }
+}
+
+impl MetaPool {
+    pub(crate) fn internal_mt_balance_of(&self, account_id: &AccountId, token_id: &str) -> u128 {
+        let acc = self.accounts.get(account_id).unwrap_or_default();
+        match token_id {
+            MT_TOKEN_NEAR => acc.available,
+            MT_TOKEN_STNEAR => acc.stake_shares,
+            MT_TOKEN_NSLP => acc.nslp_shares,
+            _ => panic!("unknown token_id {}", token_id),
+        }
+    }
+
+    fn internal_mt_supply(&self, token_id: &str) -> Option<u128> {
+        match token_id {
+            MT_TOKEN_NEAR => Some(self.total_available),
+            MT_TOKEN_STNEAR => Some(self.total_stake_shares),
+            MT_TOKEN_NSLP => Some(self.internal_get_nslp_account().nslp_shares),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn internal_mt_transfer(
+        &mut self,
+        sender_id: &AccountId,
+        receiver_id: &AccountId,
+        token_id: &str,
+        amount: u128,
+        memo: Option<&str>,
+    ) {
+        match token_id {
+            MT_TOKEN_NEAR => self.internal_available_transfer(sender_id, receiver_id, amount, memo),
+            // emits ft_transfer and its mt_transfer twin, see events::FtTransfer
+            MT_TOKEN_STNEAR => self.internal_st_near_transfer(sender_id, receiver_id, amount, memo),
+            MT_TOKEN_NSLP => self.internal_nslp_shares_transfer(sender_id, receiver_id, amount, memo),
+            _ => panic!("unknown token_id {}", token_id),
+        }
+    }
+
+    fn internal_mt_batch_transfer(
+        &mut self,
+        sender_id: &AccountId,
+        receiver_id: &AccountId,
+        token_ids: &[String],
+        amounts: &[U128],
+        approvals: Option<Vec<Option<(AccountId, u64)>>>,
+        memo: Option<&str>,
+    ) {
+        assert!(!token_ids.is_empty(), "token_ids can not be empty");
+        assert_eq!(
+            token_ids.len(),
+            amounts.len(),
+            "token_ids and amounts must have the same length"
+        );
+        if let Some(approvals) = approvals {
+            assert!(
+                approvals.iter().all(|x| x.is_none()),
+                "approvals are not supported"
+            );
+        }
+        for inx in 0..token_ids.len() {
+            self.assert_mt_transfer_allowed(&token_ids[inx]);
+            self.internal_mt_transfer(sender_id, receiver_id, &token_ids[inx], amounts[inx].0, memo);
+        }
+    }
+
+    fn internal_mt_batch_transfer_call(
+        &mut self,
+        receiver_id: AccountId,
+        token_ids: Vec<String>,
+        amounts: Vec<U128>,
+        approvals: Option<Vec<Option<(AccountId, u64)>>>,
+        memo: Option<String>,
+        msg: String,
+    ) -> PromiseOrValue<Vec<U128>> {
+        assert!(
+            env::prepaid_gas() > GAS_FOR_MT_TRANSFER_CALL + GAS_FOR_MT_RESOLVE_TRANSFER + FIVE_TGAS,
+            "gas required {}",
+            GAS_FOR_MT_TRANSFER_CALL + GAS_FOR_MT_RESOLVE_TRANSFER + FIVE_TGAS
+        );
+        let sender_id = env::predecessor_account_id();
+        self.internal_mt_batch_transfer(
+            &sender_id,
+            &receiver_id,
+            &token_ids,
+            &amounts,
+            approvals,
+            memo.as_deref(),
+        );
+
+        ext_mt_receiver::mt_on_transfer(
+            sender_id.clone(),
+            vec![sender_id.clone(); token_ids.len()],
+            token_ids.clone(),
+            amounts.clone(),
+            msg,
+            //promise params:
+            &receiver_id, //contract
+            NO_DEPOSIT,   //attached native NEAR amount
+            env::prepaid_gas() - GAS_FOR_MT_TRANSFER_CALL - GAS_FOR_MT_RESOLVE_TRANSFER - ONE_TGAS, // set almost all remaining gas for mt_on_transfer
+        )
+        .then(ext_mt_self::mt_resolve_transfer(
+            vec![sender_id; token_ids.len()],
+            receiver_id,
+            token_ids,
+            amounts,
+            //promise params:
+            &env::current_account_id(), //contract
+            NO_DEPOSIT,                 //attached native NEAR amount
+            GAS_FOR_MT_RESOLVE_TRANSFER,
+        ))
+        .into()
+    }
+
+    /// checks the pause flag of each token. Lockup accounts can not move
+    /// its available NEAR, it must return to the lockup contract
+    fn assert_mt_transfer_allowed(&self, token_id: &str) {
+        match token_id {
+            MT_TOKEN_NEAR => {
+                self.assert_operation_not_paused(PAUSE_NEAR_TRANSFER);
+                self.assert_not_lockup_account_calling();
+            }
+            MT_TOKEN_STNEAR => self.assert_operation_not_paused(PAUSE_ST_NEAR_TRANSFER),
+            MT_TOKEN_NSLP => self.assert_operation_not_paused(PAUSE_NSLP_TRANSFER),
+            _ => {}
+        }
+    }
+
+    /// returns unused tokens from receiver_id to sender_id, this fn MUST NOT PANIC
+    /// returns the amount refunded
+    fn internal_mt_refund(
+        &mut self,
+        receiver_id: &AccountId,
+        sender_id: &AccountId,
+        token_id: &str,
+        unused_amount: u128,
+    ) -> u128 {
+        if unused_amount == 0 || receiver_id == sender_id {
+            return 0;
+        }
+        // balances are moved directly, the transfer fns assert
+        let (mut receiver_acc, mut sender_acc) =
+            match (self.accounts.get(receiver_id), self.accounts.get(sender_id)) {
+                (Some(receiver_acc), Some(sender_acc)) => (receiver_acc, sender_acc),
+                _ => return 0,
+            };
+        let refund_amount = match token_id {
+            MT_TOKEN_NEAR => {
+                let refund_amount = std::cmp::min(unused_amount, receiver_acc.available);
+                receiver_acc.available -= refund_amount;
+                sender_acc.available += refund_amount;
+                refund_amount
+            }
+            MT_TOKEN_STNEAR => {
+                let refund_amount = std::cmp::min(unused_amount, receiver_acc.stake_shares);
+                let near_amount = self.amount_from_stake_shares(refund_amount);
+                receiver_acc.sub_stake_shares(refund_amount, near_amount);
+                sender_acc.add_stake_shares(refund_amount, near_amount);
+                refund_amount
+            }
+            MT_TOKEN_NSLP => {
+                let refund_amount = std::cmp::min(unused_amount, receiver_acc.nslp_shares);
+                receiver_acc.move_nslp_shares(&mut sender_acc, refund_amount);
+                refund_amount
+            }
+            _ => 0,
+        };
+        if refund_amount == 0 {
+            return 0;
+        }
+        self.internal_update_account(receiver_id, &receiver_acc);
+        self.internal_update_account(sender_id, &sender_acc);
+        if token_id == MT_TOKEN_STNEAR {
+            // emits ft_transfer and its mt_transfer twin
+            events::FtTransfer {
+                old_owner_id: receiver_id,
+                new_owner_id: sender_id,
+                amount: refund_amount.into(),
+                memo: Some("refund"),
+            }
+            .emit();
+        } else {
+            events::MtTransfer {
+                old_owner_id: receiver_id,
+                new_owner_id: sender_id,
+                token_ids: vec![token_id],
+                amounts: vec![refund_amount.into()],
+                memo: Some("refund"),
+            }
+            .emit();
+        }
+        refund_amount
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    fn balances(contract: &MetaPool, account_id: &AccountId) -> Vec<u128> {
+        contract
+            .mt_batch_balance_of(
+                account_id.clone(),
+                vec![MT_TOKEN_NEAR.into(), MT_TOKEN_STNEAR.into(), MT_TOKEN_NSLP.into()],
+            )
+            .iter()
+            .map(|x| x.0)
+            .collect()
+    }
+
+    #[test]
+    fn test_mt_transfer() {
+        let mut contract = contract_with_balances();
+        set_context(&alice(), 1);
+        contract.mt_transfer(valid(&bob()), MT_TOKEN_NEAR.into(), ntoy(4).into(), None, None);
+        contract.mt_transfer(valid(&bob()), MT_TOKEN_STNEAR.into(), ntoy(5).into(), None, None);
+        contract.mt_transfer(valid(&bob()), MT_TOKEN_NSLP.into(), ntoy(6).into(), None, None);
+
+        assert_eq!(balances(&contract, &alice()), vec![ntoy(6), ntoy(15), ntoy(24)]);
+        assert_eq!(balances(&contract, &bob()), vec![ntoy(4), ntoy(5), ntoy(6)]);
+        // supplies do not change
+        assert_eq!(contract.mt_supply(MT_TOKEN_NEAR.into()), Some(ntoy(40).into()));
+        assert_eq!(contract.mt_supply(MT_TOKEN_STNEAR.into()), Some(ntoy(120).into()));
+        assert_eq!(contract.mt_supply(MT_TOKEN_NSLP.into()), Some(ntoy(30).into()));
+        // alice's position predates the cost basis, bob's shares are untracked too
+        assert!(contract.get_lp_position(alice()).cost_basis.is_none());
+        assert!(contract.get_lp_position(bob()).cost_basis.is_none());
+    }
+
+    #[test]
+    fn test_mt_transfer_moves_cost_basis() {
+        let mut contract = contract_with_balances();
+        let mut acc = contract.internal_get_account(&alice());
+        acc.nslp_cost_basis = ntoy(120);
+        contract.internal_update_account(&alice(), &acc);
+        set_context(&alice(), 1);
+        contract.mt_transfer(valid(&bob()), MT_TOKEN_NSLP.into(), ntoy(6).into(), None, None);
+        assert_eq!(contract.get_lp_position(alice()).cost_basis, Some(ntoy(96).into()));
+        assert_eq!(contract.get_lp_position(bob()).cost_basis, Some(ntoy(24).into()));
+
+        // untracked shares make bob's position untracked
+        let acc = Account {
+            nslp_shares: ntoy(1),
+            ..Account::default()
+        };
+        contract.internal_update_account(&treasury(), &acc);
+        set_context(&treasury(), 1);
+        contract.mt_transfer(valid(&bob()), MT_TOKEN_NSLP.into(), ntoy(1).into(), None, None);
+        assert!(contract.get_lp_position(bob()).cost_basis.is_none());
+    }
+
+    #[test]
+    fn test_mt_batch_transfer() {
+        let mut contract = contract_with_balances();
+        set_context(&alice(), 1);
+        contract.mt_batch_transfer(
+            valid(&bob()),
+            vec![MT_TOKEN_STNEAR.into(), MT_TOKEN_NSLP.into()],
+            vec![ntoy(1).into(), ntoy(2).into()],
+            None,
+            None,
+        );
+        assert_eq!(balances(&contract, &bob()), vec![0, ntoy(1), ntoy(2)]);
+    }
+
+    #[test]
+    #[should_panic(expected = "not enough NSLP shares")]
+    fn test_mt_transfer_over_balance() {
+        let mut contract = contract_with_balances();
+        set_context(&alice(), 1);
+        contract.mt_transfer(valid(&bob()), MT_TOKEN_NSLP.into(), ntoy(31).into(), None, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "unknown token_id")]
+    fn test_mt_transfer_unknown_token() {
+        let mut contract = contract_with_balances();
+        set_context(&alice(), 1);
+        contract.mt_transfer(valid(&bob()), "meta".into(), ntoy(1).into(), None, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "paused")]
+    fn test_mt_transfer_paused() {
+        let mut contract = contract_with_balances();
+        contract.paused_operations = PAUSE_NSLP_TRANSFER;
+        set_context(&alice(), 1);
+        contract.mt_transfer(valid(&bob()), MT_TOKEN_NSLP.into(), ntoy(1).into(), None, None);
+    }
+
+    /// alice sent 10 stNEAR and 10 NSLP shares to bob with mt_batch_transfer_call
+    fn contract_after_transfer_call() -> MetaPool {
+        let mut contract = contract_with_balances();
+        set_context(&alice(), 1);
+        contract.mt_batch_transfer(
+            valid(&bob()),
+            vec![MT_TOKEN_STNEAR.into(), MT_TOKEN_NSLP.into()],
+            vec![ntoy(10).into(), ntoy(10).into()],
+            None,
+            None,
+        );
+        contract
+    }
+
+    fn resolve(contract: &mut MetaPool, promise_result: PromiseResult) -> Vec<u128> {
+        set_context_with_promise_result(&contract_account(), promise_result);
+        contract
+            .mt_resolve_transfer(
+                vec![alice(), alice()],
+                bob(),
+                vec![MT_TOKEN_STNEAR.into(), MT_TOKEN_NSLP.into()],
+                vec![ntoy(10).into(), ntoy(10).into()],
+            )
+            .iter()
+            .map(|x| x.0)
+            .collect()
+    }
+
+    #[test]
+    fn test_mt_resolve_transfer_partial_refund() {
+        let mut contract = contract_after_transfer_call();
+        let unused = near_sdk::serde_json::to_vec(&vec![U128(ntoy(4)), U128(0)]).unwrap();
+        let used = resolve(&mut contract, PromiseResult::Successful(unused));
+        assert_eq!(used, vec![ntoy(6), ntoy(10)]);
+        assert_eq!(balances(&contract, &alice()), vec![ntoy(10), ntoy(14), ntoy(20)]);
+        assert_eq!(balances(&contract, &bob()), vec![0, ntoy(6), ntoy(10)]);
+    }
+
+    #[test]
+    fn test_mt_resolve_transfer_failed() {
+        let mut contract = contract_after_transfer_call();
+        let used = resolve(&mut contract, PromiseResult::Failed);
+        assert_eq!(used, vec![0, 0]);
+        assert_eq!(balances(&contract, &alice()), vec![ntoy(10), ntoy(20), ntoy(30)]);
+        assert_eq!(balances(&contract, &bob()), vec![0, 0, 0]);
+    }
+
+    #[test]
+    fn test_mt_resolve_transfer_refund_capped_by_receiver_balance() {
+        let mut contract = contract_after_transfer_call();
+        // the receiver already moved part of the stNEAR
+        contract.internal_update_account(&treasury(), &Account::default());
+        contract.internal_mt_transfer(&bob(), &treasury(), MT_TOKEN_STNEAR, ntoy(8), None);
+        let used = resolve(&mut contract, PromiseResult::Failed);
+        assert_eq!(used, vec![ntoy(8), 0]);
+        assert_eq!(balances(&contract, &alice()), vec![ntoy(10), ntoy(12), ntoy(30)]);
+        assert_eq!(balances(&contract, &bob()), vec![0, 0, 0]);
+    }
+
+    #[test]
+    fn test_mt_resolve_transfer_sender_gone() {
+        let mut contract = contract_after_transfer_call();
+        contract.accounts.remove(&alice());
+        let used = resolve(&mut contract, PromiseResult::Failed);
+        assert_eq!(used, vec![ntoy(10), ntoy(10)]);
+        assert_eq!(balances(&contract, &bob()), vec![0, ntoy(10), ntoy(10)]);
+    }
+
+    #[test]
+    fn test_mt_refund_never_panics() {
+        let mut contract = contract_with_balances();
+        // same account
+        assert_eq!(contract.internal_mt_refund(&alice(), &alice(), MT_TOKEN_NEAR, ntoy(1)), 0);
+        // unknown token
+        assert_eq!(contract.internal_mt_refund(&alice(), &bob(), "unknown", ntoy(1)), 0);
+        // receiver without balance
+        assert_eq!(contract.internal_mt_refund(&bob(), &alice(), MT_TOKEN_NSLP, ntoy(1)), 0);
+        assert_eq!(contract.internal_mt_refund(&alice(), &bob(), MT_TOKEN_NEAR, ntoy(50)), ntoy(10));
+        assert_eq!(balances(&contract, &bob())[0], ntoy(10));
+    }
+}
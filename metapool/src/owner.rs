@@ -15,18 +15,85 @@ pub struct GSPRUResultJson {
 impl MetaPool {
     // OWNER'S METHODS and other general view-methods
 
-    /// Owner's method.
+    /// Pool manager's method.
     /// Pauses pool staking.
     pub fn pause_staking(&mut self) {
-        self.assert_operator_or_owner();
+        self.assert_role(Role::PoolManager);
         assert!(!self.staking_paused, "The staking is already paused");
         self.staking_paused = true;
+        events::AdminChange {
+            method: "pause_staking",
+            by: &env::predecessor_account_id(),
+            account_id: None,
+            value: None,
+        }
+        .emit();
     }
     /// unPauses pool staking.
     pub fn un_pause_staking(&mut self) {
-        self.assert_operator_or_owner();
+        self.assert_role(Role::PoolManager);
         assert!(self.staking_paused, "The staking is not paused");
         self.staking_paused = false;
+        events::AdminChange {
+            method: "un_pause_staking",
+            by: &env::predecessor_account_id(),
+            account_id: None,
+            value: None,
+        }
+        .emit();
+    }
+
+    /// Guardian's method.
+    /// Pauses the operations in the bitmask (see PAUSE_* constants), e.g. during an incident
+    pub fn pause_operations(&mut self, operations: u32) {
+        self.assert_role(Role::Guardian);
+        assert!(
+            operations != 0 && operations & !PAUSE_ALL_OPERATIONS == 0,
+            "invalid operations bitmask {}",
+            operations
+        );
+        self.paused_operations |= operations;
+        events::Pause {
+            paused: true,
+            operations,
+            paused_operations: self.paused_operations,
+            by: &env::predecessor_account_id(),
+        }
+        .emit();
+    }
+    /// Owner's method.
+    /// un-pauses the operations in the bitmask. Accounts with Role::Guardian can pause but not un-pause
+    pub fn un_pause_operations(&mut self, operations: u32) {
+        self.assert_owner_calling();
+        assert!(
+            operations != 0 && operations & !PAUSE_ALL_OPERATIONS == 0,
+            "invalid operations bitmask {}",
+            operations
+        );
+        self.paused_operations &= !operations;
+        events::Pause {
+            paused: false,
+            operations,
+            paused_operations: self.paused_operations,
+            by: &env::predecessor_account_id(),
+        }
+        .emit();
+    }
+    /// which operations are currently paused
+    pub fn get_paused_operations(&self) -> PausedOperationsJSON {
+        let paused = self.paused_operations;
+        return PausedOperationsJSON {
+            paused_operations: paused,
+            deposit: paused & PAUSE_DEPOSIT != 0,
+            delayed_unstake: paused & PAUSE_DELAYED_UNSTAKE != 0,
+            liquid_unstake: paused & PAUSE_LIQUID_UNSTAKE != 0,
+            nslp_add_liquidity: paused & PAUSE_NSLP_ADD_LIQUIDITY != 0,
+            nslp_remove_liquidity: paused & PAUSE_NSLP_REMOVE_LIQUIDITY != 0,
+            st_near_transfer: paused & PAUSE_ST_NEAR_TRANSFER != 0,
+            nslp_transfer: paused & PAUSE_NSLP_TRANSFER != 0,
+            near_transfer: paused & PAUSE_NEAR_TRANSFER != 0,
+            buy_stnear: paused & PAUSE_BUY_STNEAR != 0,
+        };
     }
 
     //---------------------------------
@@ -56,19 +123,26 @@ impl MetaPool {
 
     ///remove staking pool from list *if it's empty*
     pub fn remove_staking_pool(&mut self, inx: u16) {
-        self.assert_operator_or_owner();
+        self.assert_role(Role::PoolManager);
 
         let sp = &self.staking_pools[inx as usize];
         if !sp.is_empty() {
             panic!("sp is not empty")
         }
-        self.staking_pools.remove(inx as usize);
+        let sp = self.staking_pools.remove(inx as usize);
+        events::AdminChange {
+            method: "remove_staking_pool",
+            by: &env::predecessor_account_id(),
+            account_id: Some(&sp.account_id),
+            value: None,
+        }
+        .emit();
     }
 
     /// add a new staking pool, checking that it is not already in the list
     /// added with weight_basis_points = 0, to preserve sum(weights)=100%
     pub fn add_staking_pool(&mut self, account_id: AccountId) {
-        self.assert_operator_or_owner();
+        self.assert_role(Role::PoolManager);
         assert!(
             account_id.ends_with(".poolv1.near") 
                 || account_id.ends_with(".pool.near") 
@@ -81,6 +155,13 @@ impl MetaPool {
             "already in the list"
         );
         // not in list, add
+        events::AdminChange {
+            method: "add_staking_pool",
+            by: &env::predecessor_account_id(),
+            account_id: Some(&account_id),
+            value: None,
+        }
+        .emit();
         self.staking_pools
             .push(StakingPoolInfo::new(account_id, 0));
     }
@@ -88,19 +169,24 @@ impl MetaPool {
     /// update existing staking pools list, field weight_basis_points
     /// sum(weight_basis_points) must be eq 100%
     /// can not add, remove or change order of staking pools
+    /// timelocked, see queue_set_staking_pools
     #[payable]
     pub fn set_staking_pools(&mut self, list: Vec<StakingPoolArgItem>) {
         assert_one_yocto();
-        self.assert_operator_or_owner();
+        self.assert_role(Role::PoolManager);
+        self.assert_not_timelocked(AdminActionClass::StakingPools);
+        self.internal_set_staking_pools(list);
+    }
+    pub(crate) fn internal_set_staking_pools(&mut self, list: Vec<StakingPoolArgItem>) {
         // make sure no additions or removals
         assert_eq!(list.len(),self.staking_pools.len());
         // process the list
         let mut total_weight = 0;
-        for sp_inx in 0..list.len() {
+        for (sp_inx, item) in list.iter().enumerate() {
             // assert same order
-            assert_eq!(self.staking_pools[sp_inx].account_id, list[sp_inx].account_id);
+            assert_eq!(self.staking_pools[sp_inx].account_id, item.account_id);
             // get weight_basis_points to set
-            let bp = list[sp_inx].weight_basis_points;
+            let bp = item.weight_basis_points;
             // no staking pool can have 50% or more
             assert!(bp<5000);
             // if there's a change
@@ -114,6 +200,97 @@ impl MetaPool {
             total_weight += bp;
         }
         assert_eq!(total_weight,10000);
+        events::AdminChange {
+            method: "set_staking_pools",
+            by: &env::predecessor_account_id(),
+            account_id: None,
+            value: None,
+        }
+        .emit();
+    }
+
+    //---------------------------------
+    // lockup contracts & lockup accounts registry
+    //---------------------------------
+
+    /// lockup proxy contracts authorized to call stake_for_lockup, unstake_from_lockup_shares & withdraw_to_lockup
+    pub fn get_lockup_contracts(&self) -> Vec<AccountId> {
+        return self.lockup_contracts.clone();
+    }
+    /// account-id suffixes identifying lockup accounts
+    pub fn get_lockup_account_suffixes(&self) -> Vec<String> {
+        return self.lockup_account_suffixes.clone();
+    }
+
+    /// authorize a new lockup proxy contract (e.g. a new lockup factory, or a local test factory)
+    pub fn add_lockup_contract(&mut self, account_id: AccountId) {
+        assert!(env::is_valid_account_id(account_id.as_bytes()));
+        self.assert_owner_calling();
+        assert!(
+            !self.lockup_contracts.contains(&account_id),
+            "already in the list"
+        );
+        events::AdminChange {
+            method: "add_lockup_contract",
+            by: &env::predecessor_account_id(),
+            account_id: Some(&account_id),
+            value: None,
+        }
+        .emit();
+        self.lockup_contracts.push(account_id);
+    }
+    pub fn remove_lockup_contract(&mut self, account_id: AccountId) {
+        self.assert_owner_calling();
+        let inx = self
+            .lockup_contracts
+            .iter()
+            .position(|x| x == &account_id)
+            .expect("not in the list");
+        self.lockup_contracts.remove(inx);
+        events::AdminChange {
+            method: "remove_lockup_contract",
+            by: &env::predecessor_account_id(),
+            account_id: Some(&account_id),
+            value: None,
+        }
+        .emit();
+    }
+
+    /// register a new lockup account suffix, e.g. ".lockup.near"
+    pub fn add_lockup_account_suffix(&mut self, suffix: String) {
+        self.assert_owner_calling();
+        assert!(
+            suffix.len() > 1 && suffix.starts_with("."),
+            "suffix must start with '.'"
+        );
+        assert!(
+            !self.lockup_account_suffixes.contains(&suffix),
+            "already in the list"
+        );
+        events::AdminChange {
+            method: "add_lockup_account_suffix",
+            by: &env::predecessor_account_id(),
+            account_id: None,
+            value: Some(suffix.clone()),
+        }
+        .emit();
+        self.lockup_account_suffixes.push(suffix);
+    }
+    pub fn remove_lockup_account_suffix(&mut self, suffix: String) {
+        self.assert_owner_calling();
+        let inx = self
+            .lockup_account_suffixes
+            .iter()
+            .position(|x| x == &suffix)
+            .expect("not in the list");
+        self.lockup_account_suffixes.remove(inx);
+        events::AdminChange {
+            method: "remove_lockup_account_suffix",
+            by: &env::predecessor_account_id(),
+            account_id: None,
+            value: Some(suffix),
+        }
+        .emit();
     }
 
     //--------------------------------------------------
@@ -129,28 +306,139 @@ impl MetaPool {
     pub fn get_operator_account_id(&self) -> AccountId {
         return self.operator_account_id.clone();
     }
+    /// proposes a new operator, it takes effect when account_id calls accept_operator_account_id
     pub fn set_operator_account_id(&mut self, account_id: AccountId) {
         assert!(env::is_valid_account_id(account_id.as_bytes()));
         self.assert_owner_calling();
-        self.operator_account_id = account_id;
+        self.pending_operator = Some(self.internal_propose_account("operator", account_id));
+    }
+    pub fn accept_operator_account_id(&mut self) {
+        self.operator_account_id = self.internal_accept_account("operator", &self.pending_operator);
+        self.pending_operator = None;
         //all key accounts must be different
         self.assert_key_accounts_are_different();
     }
     pub fn get_treasury_account_id(&self) -> AccountId {
         return self.treasury_account_id.clone();
     }
+    /// timelocked, see queue_set_treasury_account_id
     pub fn set_treasury_account_id(&mut self, account_id: AccountId) {
         assert!(env::is_valid_account_id(account_id.as_bytes()));
-        self.assert_owner_calling();
-        self.treasury_account_id = account_id;
+        self.assert_role(Role::TreasuryManager);
+        self.assert_not_timelocked(AdminActionClass::TreasuryAccount);
+        self.internal_set_treasury_account_id(account_id);
+    }
+    /// proposes a new treasury, it takes effect when account_id calls accept_treasury_account_id
+    pub(crate) fn internal_set_treasury_account_id(&mut self, account_id: AccountId) {
+        self.pending_treasury = Some(self.internal_propose_account("treasury", account_id));
+    }
+    pub fn accept_treasury_account_id(&mut self) {
+        self.treasury_account_id = self.internal_accept_account("treasury", &self.pending_treasury);
+        self.pending_treasury = None;
         self.assert_key_accounts_are_different();
     }
+    /// proposes a new owner, it takes effect when owner_id calls accept_owner_id
     pub fn set_owner_id(&mut self, owner_id: AccountId) {
         assert!(env::is_valid_account_id(owner_id.as_bytes()));
         self.assert_owner_calling();
-        self.owner_account_id = owner_id.into();
+        self.pending_owner = Some(self.internal_propose_account("owner", owner_id));
+    }
+    pub fn accept_owner_id(&mut self) {
+        self.owner_account_id = self.internal_accept_account("owner", &self.pending_owner);
+        self.pending_owner = None;
         self.assert_key_accounts_are_different();
     }
+    /// owner's method, drops all pending key account proposals
+    pub fn cancel_account_transfers(&mut self) {
+        self.assert_owner_calling();
+        self.pending_owner = None;
+        self.pending_operator = None;
+        self.pending_treasury = None;
+    }
+    pub fn get_pending_account_transfers(&self) -> PendingAccountTransfersJSON {
+        PendingAccountTransfersJSON {
+            owner: self.pending_owner.clone(),
+            operator: self.pending_operator.clone(),
+            treasury: self.pending_treasury.clone(),
+        }
+    }
+
+    //---------------------------------
+    // access control roles
+    //---------------------------------
+
+    /// Owner's method. Grants `role` to `account_id`.
+    /// Role::Owner can not be granted, ownership moves with set_owner_id/accept_owner_id
+    pub fn grant_role(&mut self, role: Role, account_id: AccountId) {
+        assert!(env::is_valid_account_id(account_id.as_bytes()));
+        self.assert_owner_calling();
+        assert!(role != Role::Owner, "the Owner role can not be granted, use set_owner_id");
+        let mut granted = self.roles.get(&account_id).unwrap_or_default();
+        assert!(!granted.contains(&role), "{} already has role {:?}", account_id, role);
+        granted.push(role);
+        self.roles.insert(&account_id, &granted);
+        events::RoleChange {
+            action: "grant",
+            role,
+            account_id: &account_id,
+            by: &env::predecessor_account_id(),
+        }
+        .emit();
+    }
+    /// Owner's method. Revokes `role` from `account_id`
+    pub fn revoke_role(&mut self, role: Role, account_id: AccountId) {
+        self.assert_owner_calling();
+        self.internal_remove_role(role, &account_id);
+        events::RoleChange {
+            action: "revoke",
+            role,
+            account_id: &account_id,
+            by: &env::predecessor_account_id(),
+        }
+        .emit();
+    }
+    /// The caller gives up a granted role
+    pub fn renounce_role(&mut self, role: Role) {
+        let account_id = env::predecessor_account_id();
+        self.internal_remove_role(role, &account_id);
+        events::RoleChange {
+            action: "renounce",
+            role,
+            account_id: &account_id,
+            by: &account_id,
+        }
+        .emit();
+    }
+    fn internal_remove_role(&mut self, role: Role, account_id: &AccountId) {
+        let mut granted = self.roles.get(account_id).unwrap_or_default();
+        let inx = granted
+            .iter()
+            .position(|x| *x == role)
+            .expect("role not granted");
+        granted.remove(inx);
+        if granted.is_empty() {
+            self.roles.remove(account_id);
+        } else {
+            self.roles.insert(account_id, &granted);
+        }
+    }
+
+    /// accounts with `role` granted (does not include the owner & operator implicit roles)
+    pub fn get_role_members(&self, role: Role) -> Vec<AccountId> {
+        self.roles
+            .iter()
+            .filter(|(_, granted)| granted.contains(&role))
+            .map(|(account_id, _)| account_id)
+            .collect()
+    }
+    /// roles granted to `account_id` (does not include the owner & operator implicit roles)
+    pub fn get_account_roles(&self, account_id: AccountId) -> Vec<Role> {
+        self.roles.get(&account_id).unwrap_or_default()
+    }
+    /// true if `account_id` holds `role`, including implicit roles
+    pub fn account_has_role(&self, account_id: AccountId, role: Role) -> bool {
+        self.has_role(&account_id, role)
+    }
 
     /// The amount of tokens that were deposited to the staking pool.
     /// NOTE: The actual balance can be larger than this known deposit balance due to staking
@@ -182,8 +470,8 @@ impl MetaPool {
             available: acc.available.into(),
             st_near: acc.stake_shares.into(),
             valued_st_near: staked_near.into(),
-            realized_meta: 0.into(), // internal $META incentives have been disabled
-            meta: acc.realized_meta.into(), // send here for reference - disabled, historic data
+            realized_meta: 0.into(), // internal $META incentives have been removed, kept for wallets compat
+            meta: 0.into(),          // internal $META incentives have been removed, kept for wallets compat
             unstaked: acc.unstaked.into(),
             unstaked_requested_unlock_epoch: acc.unstaked_requested_unlock_epoch.into(),
             unstake_full_epochs_wait_left: acc
@@ -198,13 +486,13 @@ impl MetaPool {
             trip_accum_stakes: (if acc.staking_meter.delta_staked >= 0 {
                 acc.staking_meter.delta_staked as u128
             } else {
-                0 as u128
+                0
             })
             .into(),
             trip_accum_unstakes: (if acc.staking_meter.delta_staked < 0 {
                 -acc.staking_meter.delta_staked as u128
             } else {
-                0 as u128
+                0
             })
             .into(),
             trip_rewards: (staked_near + acc.trip_accum_unstakes)
@@ -236,12 +524,12 @@ impl MetaPool {
     // Note: params are not Option<String> so the user can not inadvertently set null to data by not including the argument
     pub fn set_contract_info(&mut self, web_app_url: String, auditor_account_id: String) {
         self.assert_owner_calling();
-        self.web_app_url = if web_app_url.len() > 0 {
+        self.web_app_url = if !web_app_url.is_empty() {
             Some(web_app_url)
         } else {
             None
         };
-        self.auditor_account_id = if auditor_account_id.len() > 0 {
+        self.auditor_account_id = if !auditor_account_id.is_empty() {
             Some(auditor_account_id)
         } else {
             None
@@ -274,12 +562,11 @@ impl MetaPool {
             reserve_for_unstake_claims: self.retrieved_for_unstake_claims.into(), // old name keep for backward compat
             total_stake_shares: self.total_stake_shares.into(), // stNEAR total supply
             st_near_price: self.amount_from_stake_shares(ONE_E24).into(), //how much nears are 1 stNEAR
-            total_meta: self.total_meta.into(),
             accounts_count: self.accounts.len().into(),
             staking_pools_count: self.staking_pools.len() as u16,
             nslp_liquidity: nslp_account.available.into(),
             nslp_stnear_balance: nslp_account.stake_shares.into(), //how much stnear does the nslp have?
-            nslp_target: self.nslp_liquidity_target.into(),
+            nslp_target: self.internal_nslp_liquidity_target().into(),
             nslp_share_price: self.amount_from_nslp_shares(ONE_E24, &nslp_account).into(), // price of one LP share (1e24 yocto_shares)
             nslp_total_shares: nslp_account.nslp_shares.into(), // total nspl shares. price = value/total_shares
             nslp_current_discount_basis_points: self
@@ -287,80 +574,291 @@ impl MetaPool {
             nslp_min_discount_basis_points: self.nslp_min_discount_basis_points,
             nslp_max_discount_basis_points: self.nslp_max_discount_basis_points,
             min_deposit_amount: self.min_deposit_amount.into(),
-            est_meta_rewards_stakers: self.est_meta_rewards_stakers.into(),
-            est_meta_rewards_lu: self.est_meta_rewards_lu.into(), //liquid-unstakers
-            est_meta_rewards_lp: self.est_meta_rewards_lp.into(), //liquidity-providers
-            max_meta_rewards_stakers: self.max_meta_rewards_stakers.into(),
-            max_meta_rewards_lu: self.max_meta_rewards_lu.into(), //liquid-unstakers
-            max_meta_rewards_lp: self.max_meta_rewards_lp.into(), //liquidity-providers
             unstaked_for_rebalance: self.unstaked_for_rebalance.into(), //floating for rebalance
         };
     }
 
+    /// evaluates the accounting invariants documented in struct MetaPool
+    /// (including sums over the staking pools), for monitoring.
+    /// The staking pools sums are skipped while async calls are in-flight (contract or pool busy).
+    /// The sums over the accounts are computed by pages, see check_account_invariants
+    pub fn check_invariants(&self) -> CheckInvariantsResult {
+        let mut sum_staked: u128 = 0;
+        let mut sum_unstaked: u128 = 0;
+        let mut sum_weight: u128 = 0;
+        let mut in_flight = self.contract_busy;
+        for sp in self.staking_pools.iter() {
+            sum_staked += sp.staked;
+            sum_unstaked += sp.unstaked;
+            sum_weight += sp.weight_basis_points as u128;
+            in_flight = in_flight || sp.busy_lock;
+        }
+
+        let mut checks = vec![
+            InvariantCheckJSON::at_most(
+                "contract_account_balance_vs_env",
+                "contract_account_balance <= env::account_balance()",
+                self.contract_account_balance,
+                env::account_balance(),
+            ),
+            InvariantCheckJSON::at_least(
+                "unstake_claims_coverage",
+                "retrieved_for_unstake_claims + total_unstaked_and_waiting + epoch_unstake_orders >= total_unstake_claims",
+                self.retrieved_for_unstake_claims
+                    + self.total_unstaked_and_waiting
+                    + self.epoch_unstake_orders,
+                self.total_unstake_claims,
+            ),
+        ];
+        let mut skipped: Vec<String> = vec![];
+        if in_flight {
+            skipped.push("total_actually_staked".into());
+            skipped.push("total_unstaked_and_waiting".into());
+        } else {
+            checks.push(InvariantCheckJSON::equal(
+                "total_actually_staked",
+                "total_actually_staked == sum(sp.staked)",
+                self.total_actually_staked,
+                sum_staked,
+            ));
+            checks.push(InvariantCheckJSON::equal(
+                "total_unstaked_and_waiting",
+                "total_unstaked_and_waiting == sum(sp.unstaked)",
+                self.total_unstaked_and_waiting,
+                sum_unstaked,
+            ));
+        }
+        if !self.staking_pools.is_empty() {
+            checks.push(InvariantCheckJSON::equal(
+                "staking_pools_weight",
+                "sum(sp.weight_basis_points) == 10000",
+                sum_weight,
+                10_000,
+            ));
+        }
+
+        return CheckInvariantsResult {
+            ok: checks.iter().all(|x| x.ok),
+            checks,
+            skipped,
+        };
+    }
+
+    /// sums over the accounts in [from_index, from_index+limit), for monitoring.
+    /// Adding the sums of all the pages, read at the same block height, must give
+    /// total_available == sum(accounts.available) and total_unstake_claims == sum(accounts.unstaked)
+    pub fn check_account_invariants(&self, from_index: u64, limit: u64) -> AccountInvariantsJSON {
+        let accounts = self.accounts.values_as_vector();
+        let to_index = std::cmp::min(from_index + limit, accounts.len());
+        let mut sum_available: u128 = 0;
+        let mut sum_unstaked: u128 = 0;
+        for inx in from_index..to_index {
+            let acc = accounts.get(inx).unwrap();
+            sum_available += acc.available;
+            sum_unstaked += acc.unstaked;
+        }
+        return AccountInvariantsJSON {
+            next_index: to_index.into(),
+            accounts_count: accounts.len().into(),
+            sum_available: sum_available.into(),
+            sum_unstaked: sum_unstaked.into(),
+            total_available: self.total_available.into(),
+            total_unstake_claims: self.total_unstake_claims.into(),
+        };
+    }
+
     /// Returns JSON representation of contract parameters
     pub fn get_contract_params(&self) -> ContractParamsJSON {
+        let find = |recipient: FeeRecipient| {
+            self.fee_beneficiaries
+                .iter()
+                .find(|x| x.recipient == recipient)
+        };
+        let operator = find(FeeRecipient::Operator);
+        let treasury = find(FeeRecipient::Treasury);
         return ContractParamsJSON {
             nslp_liquidity_target: self.nslp_liquidity_target.into(),
             nslp_max_discount_basis_points: self.nslp_max_discount_basis_points,
             nslp_min_discount_basis_points: self.nslp_min_discount_basis_points,
 
-            staker_meta_mult_pct: self.staker_meta_mult_pct,
-            stnear_sell_meta_mult_pct: self.stnear_sell_meta_mult_pct,
-            lp_provider_meta_mult_pct: self.lp_provider_meta_mult_pct,
-            operator_rewards_fee_basis_points: self.operator_rewards_fee_basis_points,
-            operator_swap_cut_basis_points: self.operator_swap_cut_basis_points,
-            treasury_swap_cut_basis_points: self.treasury_swap_cut_basis_points,
+            operator_rewards_fee_basis_points: operator.map_or(0, |x| x.rewards_fee_bp),
+            operator_swap_cut_basis_points: operator.map_or(0, |x| x.swap_cut_bp),
+            treasury_swap_cut_basis_points: treasury.map_or(0, |x| x.swap_cut_bp),
 
             min_deposit_amount: self.min_deposit_amount.into(),
             min_stake_unstake_amount_movement: MIN_STAKE_UNSTAKE_AMOUNT_MOVEMENT.into(),
             unstake_for_rebalance_cap_bp: self.unstake_for_rebalance_cap_bp,
+            nslp_fee_curve: Some(self.nslp_fee_curve.clone()),
+            nslp_relative_target: self.nslp_relative_target.clone(),
+            clear_nslp_relative_target: false,
+            nslp_buy_discount_basis_points: Some(self.nslp_buy_discount_basis_points),
         };
     }
 
+    /// NSLP fee curve sampled at `samples` liquidity points from 0 to the current liquidity target, for UIs
+    pub fn get_nslp_fee_curve(&self, samples: u16) -> NslpFeeCurveJSON {
+        let samples = samples.clamp(2, 101) as u128;
+        let target = self.internal_nslp_liquidity_target();
+        NslpFeeCurveJSON {
+            curve: self.nslp_fee_curve.clone(),
+            liquidity_target: target.into(),
+            points: (0..samples)
+                .map(|inx| {
+                    let liquidity = proportional(target, inx, samples - 1);
+                    NslpFeeCurvePoint {
+                        liquidity: liquidity.into(),
+                        discount_basis_points: self.internal_nslp_discount_bp_at(liquidity),
+                    }
+                })
+                .collect(),
+        }
+    }
+
     /// Sets contract parameters
+    /// timelocked, see queue_set_contract_params
     pub fn set_contract_params(&mut self, params: ContractParamsJSON) {
-        self.assert_operator_or_owner();
+        self.assert_role(Role::FeeManager);
+        self.assert_not_timelocked(AdminActionClass::ContractParams);
+        self.internal_set_contract_params(params);
+    }
+    pub(crate) fn internal_set_contract_params(&mut self, params: ContractParamsJSON) {
         assert!(params.nslp_max_discount_basis_points > params.nslp_min_discount_basis_points);
 
         self.nslp_liquidity_target = params.nslp_liquidity_target.0;
+        assert_nslp_relative_target_params(&params);
+        if params.clear_nslp_relative_target {
+            self.nslp_relative_target = None;
+        } else if let Some(relative) = &params.nslp_relative_target {
+            self.nslp_relative_target = Some(relative.clone());
+        }
         self.nslp_max_discount_basis_points = params.nslp_max_discount_basis_points;
         self.nslp_min_discount_basis_points = params.nslp_min_discount_basis_points;
+        if let Some(curve) = &params.nslp_fee_curve {
+            self.nslp_fee_curve = curve.clone();
+        }
+        if let Some(basis_points) = params.nslp_buy_discount_basis_points {
+            self.nslp_buy_discount_basis_points = basis_points;
+        }
+        assert_nslp_buy_discount_valid(
+            self.nslp_buy_discount_basis_points,
+            self.nslp_min_discount_basis_points,
+        );
+        // min & max could change, the curve must be valid for both
+        self.nslp_fee_curve.assert_valid(
+            self.nslp_min_discount_basis_points,
+            self.nslp_max_discount_basis_points,
+        );
 
-        self.staker_meta_mult_pct = params.staker_meta_mult_pct;
-        self.stnear_sell_meta_mult_pct = params.stnear_sell_meta_mult_pct;
-        self.lp_provider_meta_mult_pct = params.lp_provider_meta_mult_pct;
-
-        // note: self.operator_rewards_fee_basis_points should be only set
+        // note: the operator rewards fee should be only set
         // via pub fn set_reward_fee, which imposes a cap of 10%
-        // self.operator_rewards_fee_basis_points = params.operator_rewards_fee_basis_points;
 
-        self.operator_swap_cut_basis_points = params.operator_swap_cut_basis_points;
-        self.treasury_swap_cut_basis_points = params.treasury_swap_cut_basis_points;
+        self.internal_get_fee_beneficiary_mut(&FeeRecipient::Operator)
+            .swap_cut_bp = params.operator_swap_cut_basis_points;
+        self.internal_get_fee_beneficiary_mut(&FeeRecipient::Treasury)
+            .swap_cut_bp = params.treasury_swap_cut_basis_points;
+        self.assert_fee_beneficiaries_valid();
 
         self.min_deposit_amount = params.min_deposit_amount.0;
         assert!(params.unstake_for_rebalance_cap_bp<2000); // hard coded limit, no more than 20%
         self.unstake_for_rebalance_cap_bp = params.unstake_for_rebalance_cap_bp;
+        events::AdminChange {
+            method: "set_contract_params",
+            by: &env::predecessor_account_id(),
+            account_id: None,
+            value: Some(near_sdk::serde_json::to_string(&params).unwrap()),
+        }
+        .emit();
     }
 
-    /// Sets contract parameters
-    pub fn set_reward_multipliers(
-        &mut self,
-        stakers_pct: u16,
-        lp_pct: u16,
-        liquid_unstake_pct: u16,
-    ) {
-        self.assert_operator_or_owner();
-        self.staker_meta_mult_pct = stakers_pct;
-        self.stnear_sell_meta_mult_pct = liquid_unstake_pct;
-        self.lp_provider_meta_mult_pct = lp_pct;
+    /// fee beneficiaries, with their share of the rewards & of the liquid-unstake fee
+    pub fn get_fee_beneficiaries(&self) -> Vec<FeeBeneficiary> {
+        self.fee_beneficiaries.clone()
     }
 
-    /// Sets contract parameters
-    pub fn set_max_meta_rewards(&mut self, stakers: u32, lu: u32, lp: u32) {
-        self.assert_operator_or_owner();
-        self.max_meta_rewards_stakers = stakers as u128 * ONE_NEAR; //stakers
-        self.max_meta_rewards_lu = lu as u128 * ONE_NEAR; //liquid-unstakers
-        self.max_meta_rewards_lp = lp as u128 * ONE_NEAR; //liquidity-providers
+    /// fees received by a fee beneficiary account
+    pub fn get_fee_report(&self, account_id: AccountId) -> FeeReportJSON {
+        let accrual = self.fee_accruals.get(&account_id).unwrap_or_default();
+        let st_near_balance = self
+            .accounts
+            .get(&account_id)
+            .map_or(0, |acc| acc.stake_shares);
+        FeeReportJSON {
+            account_id,
+            rewards_fee_stnear: accrual.rewards_fee_stnear.into(),
+            swap_cut_stnear: accrual.swap_cut_stnear.into(),
+            last_epoch: accrual.last_epoch.into(),
+            auto_unstake: accrual.auto_unstake,
+            pending_auto_unstake: accrual.pending_auto_unstake.into(),
+            auto_unstaked_stnear: accrual.auto_unstaked_stnear.into(),
+            st_near_balance: st_near_balance.into(),
+        }
+    }
+
+    /// Fee beneficiary's method. When enabled, the fee stNEAR received is delayed-unstaked each epoch,
+    /// the NEAR can be withdrawn with withdraw_unstaked after the unstaking delay
+    pub fn set_fee_auto_unstake(&mut self, enabled: bool) {
+        let account_id = env::predecessor_account_id();
+        assert!(
+            self.fee_beneficiaries
+                .iter()
+                .any(|x| self.fee_recipient_account_id(&x.recipient) == account_id),
+            "{} is not a fee beneficiary",
+            account_id
+        );
+        let mut accrual = self.fee_accruals.get(&account_id).unwrap_or_default();
+        accrual.auto_unstake = enabled;
+        accrual.pending_auto_unstake = 0;
+        self.fee_accruals.insert(&account_id, &accrual);
+        events::AdminChange {
+            method: "set_fee_auto_unstake",
+            by: &account_id,
+            account_id: Some(&account_id),
+            value: Some(enabled.to_string()),
+        }
+        .emit();
+    }
+
+    /// Replaces the fee beneficiaries list.
+    /// sum(rewards_fee_bp) <= 10%, sum(swap_cut_bp) < 100%.
+    /// The developers entry (DEVELOPERS_ACCOUNT_ID) is required, at no less than its constant values
+    /// timelocked, see queue_set_fee_beneficiaries
+    #[payable]
+    pub fn set_fee_beneficiaries(&mut self, list: Vec<FeeBeneficiary>) {
+        assert_one_yocto();
+        self.assert_role(Role::FeeManager);
+        self.assert_not_timelocked(AdminActionClass::RewardFee);
+        self.internal_set_fee_beneficiaries(list);
+    }
+    pub(crate) fn internal_set_fee_beneficiaries(&mut self, list: Vec<FeeBeneficiary>) {
+        self.fee_beneficiaries = list;
+        self.assert_fee_beneficiaries_valid();
+        self.assert_key_accounts_are_different();
+        events::AdminChange {
+            method: "set_fee_beneficiaries",
+            by: &env::predecessor_account_id(),
+            account_id: None,
+            value: Some(near_sdk::serde_json::to_string(&self.fee_beneficiaries).unwrap()),
+        }
+        .emit();
+    }
+
+    /// Sets how the gas rebate is routed (operator, treasury & staking)
+    /// timelocked, see queue_set_gas_rebate_split
+    pub fn set_gas_rebate_split(&mut self, split: GasRebateSplit) {
+        self.assert_role(Role::FeeManager);
+        self.assert_not_timelocked(AdminActionClass::RewardFee);
+        self.internal_set_gas_rebate_split(split);
+    }
+    pub(crate) fn internal_set_gas_rebate_split(&mut self, split: GasRebateSplit) {
+        split.assert_valid();
+        self.gas_rebate_split = split;
+        events::AdminChange {
+            method: "set_gas_rebate_split",
+            by: &env::predecessor_account_id(),
+            account_id: None,
+            value: Some(near_sdk::serde_json::to_string(&split).unwrap()),
+        }
+        .emit();
     }
 
     /// get sp (staking-pool) info
@@ -372,7 +870,7 @@ impl MetaPool {
         return StakingPoolJSONInfo {
             inx,
             account_id: sp.account_id.clone(),
-            weight_basis_points: sp.weight_basis_points.clone(),
+            weight_basis_points: sp.weight_basis_points,
             staked: sp.staked.into(),
             unstaked: sp.unstaked.into(),
             unstaked_requested_epoch_height: sp.unstk_req_epoch_height.into(),
@@ -394,3 +892,243 @@ impl MetaPool {
     }
 
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    /// an owner-only method and a call to it
+    type OwnerOnlyCall = (&'static str, Box<dyn Fn(&mut MetaPool)>);
+
+    /// bob holds a Role::Owner entry in `roles`, e.g. written by an older code
+    fn contract_with_owner_entry() -> MetaPool {
+        let mut contract = new_contract();
+        contract.roles.insert(&bob(), &vec![Role::Owner]);
+        contract
+    }
+
+    #[test]
+    #[should_panic(expected = "the Owner role can not be granted")]
+    fn test_grant_owner_role() {
+        let mut contract = new_contract();
+        set_context(&owner(), 0);
+        contract.grant_role(Role::Owner, bob());
+    }
+
+    #[test]
+    fn test_owner_entry_grants_nothing() {
+        let contract = contract_with_owner_entry();
+        for role in [
+            Role::Owner,
+            Role::Operator,
+            Role::Guardian,
+            Role::FeeManager,
+        ]
+        .iter()
+        {
+            assert!(!contract.has_role(&bob(), *role));
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Can only be called by the owner")]
+    fn test_owner_entry_can_not_grant_roles() {
+        let mut contract = contract_with_owner_entry();
+        set_context(&bob(), 0);
+        contract.grant_role(Role::Guardian, alice());
+    }
+
+    #[test]
+    #[should_panic(expected = "Can only be called by the owner")]
+    fn test_owner_entry_can_not_propose_owner() {
+        let mut contract = contract_with_owner_entry();
+        set_context(&bob(), 0);
+        contract.set_owner_id(bob());
+    }
+
+    #[test]
+    #[should_panic(expected = "Can only be called by the owner")]
+    fn test_owner_entry_can_not_un_pause() {
+        let mut contract = contract_with_owner_entry();
+        contract.paused_operations = PAUSE_DEPOSIT;
+        set_context(&bob(), 0);
+        contract.un_pause_operations(PAUSE_DEPOSIT);
+    }
+
+    #[test]
+    fn test_accept_owner_moves_control() {
+        let mut contract = new_contract();
+        set_context(&owner(), 0);
+        contract.set_owner_id(bob());
+        set_context(&bob(), 0);
+        contract.accept_owner_id();
+        assert_eq!(contract.owner_account_id, bob());
+
+        let roles = [
+            Role::Owner,
+            Role::Operator,
+            Role::Guardian,
+            Role::FeeManager,
+            Role::PoolManager,
+            Role::TreasuryManager,
+        ];
+        for role in roles.iter() {
+            assert!(contract.has_role(&bob(), *role));
+            assert!(!contract.has_role(&owner(), *role));
+        }
+
+        // every owner-only method rejects the previous owner
+        let owner_only: Vec<OwnerOnlyCall> = vec![
+            (
+                "grant_role",
+                Box::new(|c| c.grant_role(Role::Guardian, alice())),
+            ),
+            (
+                "revoke_role",
+                Box::new(|c| c.revoke_role(Role::Guardian, alice())),
+            ),
+            ("set_owner_id", Box::new(|c| c.set_owner_id(owner()))),
+            (
+                "cancel_account_transfers",
+                Box::new(|c| c.cancel_account_transfers()),
+            ),
+            (
+                "un_pause_operations",
+                Box::new(|c| c.un_pause_operations(PAUSE_DEPOSIT)),
+            ),
+            ("stage_upgrade", Box::new(|c| c.stage_upgrade())),
+            (
+                "set_operator_account_id",
+                Box::new(|c| c.set_operator_account_id(alice())),
+            ),
+            (
+                "add_lockup_contract",
+                Box::new(|c| c.add_lockup_contract(alice())),
+            ),
+            (
+                "set_contract_info",
+                Box::new(|c| c.set_contract_info("https://x".into(), alice())),
+            ),
+            (
+                "set_timelock_delay",
+                Box::new(|c| c.set_timelock_delay(AdminActionClass::RewardFee, 60)),
+            ),
+            (
+                "queue_set_timelock_delay",
+                Box::new(|c| {
+                    c.queue_set_timelock_delay(AdminActionClass::RewardFee, 60);
+                }),
+            ),
+        ];
+        for (name, call) in owner_only.iter() {
+            set_context(&owner(), 1);
+            let result =
+                std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| call(&mut contract)));
+            let message = match result {
+                Ok(_) => panic!("the previous owner can still call {}", name),
+                Err(payload) => match payload.downcast::<String>() {
+                    Ok(message) => *message,
+                    Err(payload) => payload.downcast::<&str>().unwrap().to_string(),
+                },
+            };
+            assert!(
+                message.starts_with("Can only be called by the owner"),
+                "{}: unexpected panic {}",
+                name,
+                message
+            );
+        }
+    }
+
+    #[test]
+    fn test_check_account_invariants_by_pages() {
+        let contract = contract_with_balances();
+        let mut from_index = 0;
+        let mut sum_available = 0;
+        loop {
+            let page = contract.check_account_invariants(from_index, 2);
+            sum_available += page.sum_available.0;
+            assert_eq!(page.total_available.0, ntoy(40));
+            from_index = page.next_index.0;
+            if from_index == page.accounts_count.0 {
+                break;
+            }
+        }
+        assert_eq!(from_index, 3);
+        assert_eq!(sum_available, contract.total_available);
+        assert!(contract.check_invariants().ok);
+    }
+
+    fn beneficiaries_with_developers(rewards_fee_bp: u16, swap_cut_bp: u16) -> Vec<FeeBeneficiary> {
+        let mut list = default_fee_beneficiaries();
+        list[2].rewards_fee_bp = rewards_fee_bp;
+        list[2].swap_cut_bp = swap_cut_bp;
+        list.push(FeeBeneficiary {
+            recipient: FeeRecipient::Account(alice()),
+            rewards_fee_bp: 10,
+            swap_cut_bp: 100,
+        });
+        list
+    }
+
+    #[test]
+    fn test_set_fee_beneficiaries() {
+        let mut contract = new_contract();
+        set_context(&owner(), 1);
+        contract.set_fee_beneficiaries(beneficiaries_with_developers(
+            DEVELOPERS_REWARDS_FEE_BASIS_POINTS + 10,
+            DEVELOPERS_SWAP_CUT_BASIS_POINTS,
+        ));
+        assert_eq!(contract.fee_beneficiaries.len(), 4);
+    }
+
+    #[test]
+    #[should_panic(expected = "the developers.near fee beneficiary is required")]
+    fn test_set_fee_beneficiaries_without_developers() {
+        let mut contract = new_contract();
+        set_context(&owner(), 1);
+        let mut list = beneficiaries_with_developers(
+            DEVELOPERS_REWARDS_FEE_BASIS_POINTS,
+            DEVELOPERS_SWAP_CUT_BASIS_POINTS,
+        );
+        list.remove(2);
+        contract.set_fee_beneficiaries(list);
+    }
+
+    #[test]
+    #[should_panic(expected = "the developers.near fee beneficiary is required")]
+    fn test_set_fee_beneficiaries_lowers_developers() {
+        let mut contract = new_contract();
+        set_context(&owner(), 1);
+        contract.set_fee_beneficiaries(beneficiaries_with_developers(
+            DEVELOPERS_REWARDS_FEE_BASIS_POINTS,
+            DEVELOPERS_SWAP_CUT_BASIS_POINTS - 1,
+        ));
+    }
+
+    #[test]
+    fn test_pause_un_pause_operations() {
+        let mut contract = new_contract();
+        set_context(&owner(), 0);
+        contract.pause_operations(PAUSE_DEPOSIT | PAUSE_BUY_STNEAR);
+        contract.un_pause_operations(PAUSE_DEPOSIT);
+        assert_eq!(contract.paused_operations, PAUSE_BUY_STNEAR);
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid operations bitmask 0")]
+    fn test_un_pause_no_operations() {
+        let mut contract = new_contract();
+        set_context(&owner(), 0);
+        contract.un_pause_operations(0);
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid operations bitmask")]
+    fn test_un_pause_unknown_operations() {
+        let mut contract = new_contract();
+        set_context(&owner(), 0);
+        contract.un_pause_operations(!PAUSE_ALL_OPERATIONS);
+    }
+}
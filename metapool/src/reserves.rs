@@ -0,0 +1,178 @@
+use crate::*;
+use near_sdk::{log, near_bindgen, Promise, PromiseResult};
+
+/// each pool is queried with gas::staking_pool::GET_ACCOUNT_TOTAL_BALANCE, keep batches within 300 Tgas
+const MAX_RESERVES_BATCH: u16 = 8;
+
+#[near_bindgen]
+impl MetaPool {
+    //---------------------------------
+    // proof of reserves
+    //---------------------------------
+    // verify_reserves asks every staking pool for our total balance (get_account_total_balance)
+    // and compares it with our internal accounting. Call it in batches: from_index=0 starts a new round,
+    // when the last pool is verified the snapshot is completed adding env::account_balance()
+    // and comparing the total reserves with the liabilities
+
+    /// Operator's method. Verify reserves on staking_pools[from_index..from_index+limit]
+    pub fn verify_reserves(&mut self, from_index: u16, limit: u16) -> Promise {
+        self.assert_operator_or_owner();
+        assert!(limit > 0 && limit <= MAX_RESERVES_BATCH, "limit must be 1..{}", MAX_RESERVES_BATCH);
+        let pools_count = self.staking_pools.len();
+        assert!((from_index as usize) < pools_count, "from_index out of range");
+        assert!(
+            env::prepaid_gas()
+                >= limit as u64 * gas::staking_pool::GET_ACCOUNT_TOTAL_BALANCE
+                    + gas::owner_callbacks::ON_VERIFY_RESERVES
+                    + gas::BASE_GAS,
+            "not enough gas for {} pools",
+            limit
+        );
+
+        if from_index == 0 {
+            // start a new round
+            self.reserves_snapshot = Some(ReservesSnapshot {
+                verified_by: env::predecessor_account_id(),
+                started_at: env::block_timestamp().into(),
+                completed_at: None,
+                pools: self
+                    .staking_pools
+                    .iter()
+                    .map(|sp| PoolReserves {
+                        account_id: sp.account_id.clone(),
+                        reported: None,
+                        recorded: sp.total_balance().into(),
+                        busy: false,
+                        ok: false,
+                    })
+                    .collect(),
+                pools_total: 0.into(),
+                contract_balance: 0.into(),
+                nslp_liquidity: 0.into(),
+                retrieved_for_unstake_claims: 0.into(),
+                total_reserves: 0.into(),
+                total_liabilities: 0.into(),
+                ok: false,
+            });
+        } else {
+            let snapshot = self
+                .reserves_snapshot
+                .as_ref()
+                .expect("no verification in progress, start with from_index=0");
+            assert!(snapshot.completed_at.is_none(), "verification completed, start with from_index=0");
+            assert_eq!(snapshot.pools.len(), pools_count, "staking pools changed, start with from_index=0");
+        }
+
+        let to_index = std::cmp::min(from_index as usize + limit as usize, pools_count);
+        let batch_pools = &self.staking_pools[from_index as usize..to_index];
+        let account_ids: Vec<AccountId> = batch_pools.iter().map(|sp| sp.account_id.clone()).collect();
+        let mut batch: Option<Promise> = None;
+        for sp in batch_pools {
+            let query = ext_staking_pool::get_account_total_balance(
+                env::current_account_id(),
+                //promise params
+                &sp.account_id,
+                NO_DEPOSIT,
+                gas::staking_pool::GET_ACCOUNT_TOTAL_BALANCE,
+            );
+            batch = Some(match batch {
+                None => query,
+                Some(promise) => promise.and(query),
+            });
+        }
+        batch
+            .unwrap()
+            .then(ext_self_owner::on_verify_reserves(
+                from_index,
+                account_ids,
+                //promise params
+                &env::current_account_id(),
+                NO_DEPOSIT,
+                gas::owner_callbacks::ON_VERIFY_RESERVES,
+            ))
+    }
+
+    /// prev fn continues here
+    /// one promise result per pool in the batch, `account_ids` are the pools queried
+    #[private]
+    pub fn on_verify_reserves(&mut self, from_index: u16, account_ids: Vec<AccountId>) {
+        let mut snapshot = match self.reserves_snapshot.take() {
+            Some(snapshot) => snapshot,
+            None => return,
+        };
+        let pools_changed = snapshot.pools.len() != self.staking_pools.len()
+            || account_ids.iter().enumerate().any(|(n, account_id)| {
+                let inx = from_index as usize + n;
+                inx >= self.staking_pools.len()
+                    || &self.staking_pools[inx].account_id != account_id
+                    || &snapshot.pools[inx].account_id != account_id
+            });
+        if pools_changed {
+            // staking pools changed while in flight, the round must be restarted
+            log!("staking pools changed, verify_reserves must restart from_index=0");
+            self.reserves_snapshot = Some(snapshot);
+            return;
+        }
+
+        let count = account_ids.len();
+        for n in 0..count {
+            let inx = from_index as usize + n;
+            let sp = &self.staking_pools[inx];
+            // stake/unstake/withdraw in flight, the pool balance and our accounting can not be compared
+            let busy = self.contract_busy || sp.busy_lock;
+            let reported: Option<u128> = match env::promise_result(n as u64) {
+                PromiseResult::Successful(value) => {
+                    near_sdk::serde_json::from_slice::<U128String>(&value)
+                        .ok()
+                        .map(|x| x.0)
+                }
+                _ => None,
+            };
+            let recorded = sp.total_balance();
+            snapshot.pools[inx] = PoolReserves {
+                account_id: sp.account_id.clone(),
+                reported: reported.map(|x| x.into()),
+                recorded: recorded.into(),
+                busy,
+                ok: !busy && reported.filter(|x| *x >= recorded).is_some(),
+            };
+        }
+
+        // last batch? complete the snapshot
+        if from_index as usize + count == snapshot.pools.len() {
+            let pools_total: u128 = snapshot
+                .pools
+                .iter()
+                .map(|x| x.reported.map_or(0, |r| r.0))
+                .sum();
+            let contract_balance = env::account_balance();
+            let total_reserves = pools_total + contract_balance;
+            let total_liabilities =
+                self.total_for_staking + self.total_unstake_claims + self.total_available;
+
+            snapshot.completed_at = Some(env::block_timestamp().into());
+            snapshot.pools_total = pools_total.into();
+            snapshot.contract_balance = contract_balance.into();
+            snapshot.nslp_liquidity = self.internal_get_nslp_account().available.into();
+            snapshot.retrieved_for_unstake_claims = self.retrieved_for_unstake_claims.into();
+            snapshot.total_reserves = total_reserves.into();
+            snapshot.total_liabilities = total_liabilities.into();
+            snapshot.ok =
+                snapshot.pools.iter().all(|x| x.ok) && total_reserves >= total_liabilities;
+
+            events::Reserves {
+                total_reserves: total_reserves.into(),
+                total_liabilities: total_liabilities.into(),
+                ok: snapshot.ok,
+                by: &snapshot.verified_by,
+            }
+            .emit();
+        }
+        self.reserves_snapshot = Some(snapshot);
+    }
+
+    /// last reserves snapshot (completed_at is None while a round is in progress)
+    pub fn get_reserves_snapshot(&self) -> Option<ReservesSnapshot> {
+        self.reserves_snapshot.clone()
+    }
+}
@@ -26,18 +26,18 @@ impl Default for RewardMeter {
 impl RewardMeter {
     ///register a stake (to be able to compute rewards later)
     pub fn stake(&mut self, value: u128) {
-        assert!(value <= std::i128::MAX as u128);
+        assert!(value <= i128::MAX as u128);
         self.delta_staked += value as i128;
     }
     ///register a unstake (to be able to compute rewards later)
     pub fn unstake(&mut self, value: u128) {
-        assert!(value <= std::i128::MAX as u128);
+        assert!(value <= i128::MAX as u128);
         self.delta_staked -= value as i128;
     }
 
     #[inline]
     pub fn reset(&mut self, valued_shares: u128) {
-        assert!(valued_shares <= std::i128::MAX as u128);
+        assert!(valued_shares <= i128::MAX as u128);
         self.delta_staked = valued_shares as i128; // reset meter to Zero difference
     }
 }
@@ -40,7 +40,7 @@ pub struct StakingPoolInfo {
 
 impl StakingPoolInfo {
     pub fn is_empty(&self) -> bool {
-        return self.busy_lock == false
+        return !self.busy_lock
             && self.weight_basis_points == 0
             && self.staked == 0
             && self.unstaked == 0;
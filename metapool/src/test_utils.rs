@@ -0,0 +1,101 @@
+use near_sdk::json_types::ValidAccountId;
+use near_sdk::test_utils::VMContextBuilder;
+use near_sdk::{testing_env, MockedBlockchain, PromiseResult};
+use std::convert::TryFrom;
+
+use crate::*;
+
+pub fn contract_account() -> AccountId {
+    "meta-pool.near".to_string()
+}
+pub fn owner() -> AccountId {
+    "owner.near".to_string()
+}
+pub fn operator() -> AccountId {
+    "operator.near".to_string()
+}
+pub fn treasury() -> AccountId {
+    "treasury.near".to_string()
+}
+pub fn alice() -> AccountId {
+    "alice.near".to_string()
+}
+pub fn bob() -> AccountId {
+    "bob.near".to_string()
+}
+
+pub fn ntoy(near_amount: u128) -> u128 {
+    near_amount * NEAR
+}
+
+pub fn valid(account_id: &str) -> ValidAccountId {
+    ValidAccountId::try_from(account_id).unwrap()
+}
+
+/// context of a call from `predecessor` attaching `deposit`
+pub fn get_context(predecessor: &str, deposit: u128) -> VMContextBuilder {
+    let mut builder = VMContextBuilder::new();
+    builder
+        .current_account_id(valid(&contract_account()))
+        .signer_account_id(valid(predecessor))
+        .predecessor_account_id(valid(predecessor))
+        .account_balance(ntoy(1_000))
+        .attached_deposit(deposit);
+    builder
+}
+
+/// sets the context of the next call, the contract storage is kept
+pub fn set_context(predecessor: &str, deposit: u128) {
+    testing_env!(get_context(predecessor, deposit).build());
+}
+
+/// sets the context of a callback receiving `promise_result`, the contract storage is kept
+pub fn set_context_with_promise_result(predecessor: &str, promise_result: PromiseResult) {
+    let storage = near_sdk::env::take_blockchain_interface()
+        .unwrap()
+        .as_mut_mocked_blockchain()
+        .unwrap()
+        .take_storage();
+
+    near_sdk::env::set_blockchain_interface(Box::new(MockedBlockchain::new(
+        get_context(predecessor, 0).build(),
+        Default::default(),
+        Default::default(),
+        vec![promise_result],
+        storage,
+        Default::default(),
+        Default::default(),
+    )));
+}
+
+/// fresh contract, no timelock delays, no staking pools
+pub fn new_contract() -> MetaPool {
+    set_context(&owner(), 0);
+    MetaPool::new(owner(), treasury(), operator(), "meta-token.near".to_string())
+}
+
+/// stNEAR price is 1 NEAR.
+/// The NSLP holds 30 NEAR and 100 stNEAR, all its 30 shares owned by alice.
+/// alice holds 10 NEAR available and 20 stNEAR, bob is registered
+pub fn contract_with_balances() -> MetaPool {
+    let mut contract = new_contract();
+    let nslp_account = Account {
+        available: ntoy(30),
+        stake_shares: ntoy(100),
+        nslp_shares: ntoy(30),
+        ..Account::default()
+    };
+    contract.internal_update_account(&NSLP_INTERNAL_ACCOUNT.into(), &nslp_account);
+    let alice_account = Account {
+        available: ntoy(10),
+        stake_shares: ntoy(20),
+        nslp_shares: ntoy(30),
+        ..Account::default()
+    };
+    contract.internal_update_account(&alice(), &alice_account);
+    contract.internal_update_account(&bob(), &Account::default());
+    contract.total_available = ntoy(40);
+    contract.total_for_staking = ntoy(120);
+    contract.total_stake_shares = ntoy(120);
+    contract
+}
@@ -0,0 +1,304 @@
+use crate::*;
+use near_sdk::near_bindgen;
+
+const NANOSECONDS_PER_SECOND: u64 = 1_000_000_000;
+
+#[near_bindgen]
+impl MetaPool {
+    //---------------------------------
+    // timelocked admin actions
+    //---------------------------------
+    // set_contract_params, set_reward_fee, set_staking_pools & set_treasury_account_id
+    // must be queued and can only be executed after the delay of its class,
+    // so stNEAR holders have time to exit before the change takes effect.
+    // Accounts with Role::Guardian (and the owner) can cancel a queued action
+
+    /// Fee manager's method. Queue a set_contract_params
+    pub fn queue_set_contract_params(&mut self, params: ContractParamsJSON) -> u32 {
+        assert!(params.nslp_max_discount_basis_points > params.nslp_min_discount_basis_points);
+        assert!(params.unstake_for_rebalance_cap_bp < 2000);
+        assert_nslp_relative_target_params(&params);
+        assert_nslp_buy_discount_valid(
+            params
+                .nslp_buy_discount_basis_points
+                .unwrap_or(self.nslp_buy_discount_basis_points),
+            params.nslp_min_discount_basis_points,
+        );
+        if let Some(curve) = &params.nslp_fee_curve {
+            curve.assert_valid(
+                params.nslp_min_discount_basis_points,
+                params.nslp_max_discount_basis_points,
+            );
+        }
+        self.internal_queue_admin_action(AdminAction::SetContractParams(params))
+    }
+
+    /// Fee manager's method. Queue a set_reward_fee
+    #[payable]
+    pub fn queue_set_reward_fee(&mut self, basis_points: u16) -> u32 {
+        assert_one_yocto();
+        assert!(basis_points <= 1000); // less than or equal 10%
+        self.internal_queue_admin_action(AdminAction::SetRewardFee(basis_points))
+    }
+
+    /// Pool manager's method. Queue a set_staking_pools
+    #[payable]
+    pub fn queue_set_staking_pools(&mut self, list: Vec<StakingPoolArgItem>) -> u32 {
+        assert_one_yocto();
+        assert_eq!(list.len(), self.staking_pools.len());
+        self.internal_queue_admin_action(AdminAction::SetStakingPools(list))
+    }
+
+    /// Treasury manager's method. Queue a set_treasury_account_id
+    pub fn queue_set_treasury_account_id(&mut self, account_id: AccountId) -> u32 {
+        assert!(env::is_valid_account_id(account_id.as_bytes()));
+        self.internal_queue_admin_action(AdminAction::SetTreasuryAccountId(account_id))
+    }
+
+    /// Fee manager's method. Queue a set_fee_beneficiaries
+    #[payable]
+    pub fn queue_set_fee_beneficiaries(&mut self, list: Vec<FeeBeneficiary>) -> u32 {
+        assert_one_yocto();
+        assert!(list.len() <= MAX_FEE_BENEFICIARIES, "too many fee beneficiaries");
+        self.internal_queue_admin_action(AdminAction::SetFeeBeneficiaries(list))
+    }
+
+    /// Fee manager's method. Queue a set_gas_rebate_split
+    pub fn queue_set_gas_rebate_split(&mut self, split: GasRebateSplit) -> u32 {
+        split.assert_valid();
+        self.internal_queue_admin_action(AdminAction::SetGasRebateSplit(split))
+    }
+
+    /// Owner's method. Queue a timelock delay change,
+    /// lowering a delay must wait the current delay of that class
+    pub fn queue_set_timelock_delay(&mut self, class: AdminActionClass, seconds: u32) -> u32 {
+        assert!(seconds <= MAX_TIMELOCK_DELAY_SECONDS, "delay too long");
+        self.internal_queue_admin_action(AdminAction::SetTimelockDelay { class, seconds })
+    }
+
+    /// Owner's method. Raising a delay takes effect immediately
+    pub fn set_timelock_delay(&mut self, class: AdminActionClass, seconds: u32) {
+        self.assert_owner_calling();
+        assert!(seconds <= MAX_TIMELOCK_DELAY_SECONDS, "delay too long");
+        assert!(
+            seconds >= self.timelock_delays.get(class),
+            "lowering a delay must be queued, use queue_set_timelock_delay"
+        );
+        self.internal_set_timelock_delay(class, seconds);
+    }
+
+    /// executes a queued action once its delay has passed.
+    /// Must be called by an account with the same role required to queue it
+    pub fn execute_admin_action(&mut self, id: u32) {
+        let inx = self.internal_pending_admin_action_inx(id);
+        let pending = &self.pending_admin_actions[inx];
+        let action = AdminAction::from(&pending.action);
+        self.assert_role(action.role());
+        assert!(
+            env::block_timestamp() >= pending.executable_at,
+            "action {} can not be executed before {}",
+            id,
+            pending.executable_at
+        );
+        self.pending_admin_actions.remove(inx);
+        events::TimelockedAction {
+            action: "execute",
+            id,
+            class: action.class(),
+            executable_at: None,
+            by: &env::predecessor_account_id(),
+        }
+        .emit();
+        match action {
+            AdminAction::SetContractParams(params) => self.internal_set_contract_params(params),
+            AdminAction::SetRewardFee(basis_points) => self.internal_set_reward_fee(basis_points),
+            AdminAction::SetStakingPools(list) => self.internal_set_staking_pools(list),
+            AdminAction::SetTreasuryAccountId(account_id) => {
+                self.internal_set_treasury_account_id(account_id)
+            }
+            AdminAction::SetTimelockDelay { class, seconds } => {
+                self.internal_set_timelock_delay(class, seconds)
+            }
+            AdminAction::SetGasRebateSplit(split) => self.internal_set_gas_rebate_split(split),
+            AdminAction::SetFeeBeneficiaries(list) => self.internal_set_fee_beneficiaries(list),
+        }
+    }
+
+    /// cancels a queued action. Callable by accounts with Role::Guardian
+    /// and by accounts with the role required to queue it
+    pub fn cancel_admin_action(&mut self, id: u32) {
+        let inx = self.internal_pending_admin_action_inx(id);
+        let caller = env::predecessor_account_id();
+        let action = AdminAction::from(&self.pending_admin_actions[inx].action);
+        assert!(
+            self.has_role(&caller, Role::Guardian) || self.has_role(&caller, action.role()),
+            "Can only be called by a Guardian or an account with the role to queue the action"
+        );
+        self.pending_admin_actions.remove(inx);
+        events::TimelockedAction {
+            action: "cancel",
+            id,
+            class: action.class(),
+            executable_at: None,
+            by: &caller,
+        }
+        .emit();
+    }
+
+    /// queued actions with its earliest execution time
+    pub fn get_pending_admin_actions(&self) -> Vec<PendingAdminAction> {
+        self.pending_admin_actions
+            .iter()
+            .map(PendingAdminAction::from)
+            .collect()
+    }
+
+    pub fn get_timelock_delays(&self) -> TimelockDelays {
+        self.timelock_delays.clone()
+    }
+}
+
+/*****************************/
+/* timelock internal methods */
+/*****************************/
+impl MetaPool {
+    /// direct set_xxx methods are only enabled when the class has no delay
+    pub fn assert_not_timelocked(&self, class: AdminActionClass) {
+        assert!(
+            self.timelock_delays.get(class) == 0,
+            "{:?} changes are timelocked, use the queue_set_xxx methods",
+            class
+        );
+    }
+
+    fn internal_queue_admin_action(&mut self, action: AdminAction) -> u32 {
+        self.assert_role(action.role());
+        let id = self.next_admin_action_id;
+        self.next_admin_action_id += 1;
+        let queued_at = env::block_timestamp();
+        let executable_at =
+            queued_at + self.timelock_delays.get(action.class()) as u64 * NANOSECONDS_PER_SECOND;
+        events::TimelockedAction {
+            action: "queue",
+            id,
+            class: action.class(),
+            executable_at: Some(executable_at.into()),
+            by: &env::predecessor_account_id(),
+        }
+        .emit();
+        self.pending_admin_actions.push(StoredPendingAdminAction {
+            id,
+            action: action.into(),
+            queued_by: env::predecessor_account_id(),
+            queued_at,
+            executable_at,
+        });
+        id
+    }
+
+    fn internal_pending_admin_action_inx(&self, id: u32) -> usize {
+        match self.pending_admin_actions.iter().position(|x| x.id == id) {
+            Some(inx) => inx,
+            None => panic!("no pending admin action with id {}", id),
+        }
+    }
+
+    fn internal_set_timelock_delay(&mut self, class: AdminActionClass, seconds: u32) {
+        self.timelock_delays.set(class, seconds);
+        events::TimelockDelay { class, seconds }.emit();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use near_sdk::{testing_env, MockedBlockchain};
+
+    const DELAY_SECONDS: u32 = 3600;
+
+    /// contract with a reward fee delay of DELAY_SECONDS
+    fn timelocked_contract() -> MetaPool {
+        let mut contract = new_contract();
+        contract.set_timelock_delay(AdminActionClass::RewardFee, DELAY_SECONDS);
+        contract
+    }
+
+    fn set_context_at(predecessor: &str, deposit: u128, seconds: u64) {
+        testing_env!(get_context(predecessor, deposit)
+            .block_timestamp(seconds * NANOSECONDS_PER_SECOND)
+            .build());
+    }
+
+    #[test]
+    #[should_panic(expected = "timelocked")]
+    fn test_direct_set_is_timelocked() {
+        let mut contract = timelocked_contract();
+        set_context(&owner(), 1);
+        contract.set_reward_fee(100);
+    }
+
+    #[test]
+    fn test_queue_and_execute_after_delay() {
+        let mut contract = timelocked_contract();
+        set_context(&owner(), 1);
+        let id = contract.queue_set_reward_fee(100);
+        let pending = contract.get_pending_admin_actions();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].id, id);
+        assert_eq!(pending[0].executable_at.0, DELAY_SECONDS as u64 * NANOSECONDS_PER_SECOND);
+
+        set_context_at(&owner(), 0, DELAY_SECONDS as u64);
+        contract.execute_admin_action(id);
+        assert_eq!(contract.get_reward_fee_bp(), 100);
+        assert!(contract.get_pending_admin_actions().is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "can not be executed before")]
+    fn test_execute_before_delay() {
+        let mut contract = timelocked_contract();
+        set_context(&owner(), 1);
+        let id = contract.queue_set_reward_fee(100);
+        set_context_at(&owner(), 0, DELAY_SECONDS as u64 - 1);
+        contract.execute_admin_action(id);
+    }
+
+    #[test]
+    fn test_guardian_cancels() {
+        let mut contract = timelocked_contract();
+        contract.grant_role(Role::Guardian, bob());
+        set_context(&owner(), 1);
+        let id = contract.queue_set_reward_fee(100);
+        set_context(&bob(), 0);
+        contract.cancel_admin_action(id);
+        assert!(contract.get_pending_admin_actions().is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "Can only be called by a Guardian")]
+    fn test_cancel_without_role() {
+        let mut contract = timelocked_contract();
+        set_context(&owner(), 1);
+        let id = contract.queue_set_reward_fee(100);
+        set_context(&alice(), 0);
+        contract.cancel_admin_action(id);
+    }
+
+    #[test]
+    fn test_stored_action_round_trip() {
+        let action = AdminAction::SetFeeBeneficiaries(vec![FeeBeneficiary {
+            recipient: FeeRecipient::Account(alice()),
+            rewards_fee_bp: 10,
+            swap_cut_bp: 20,
+        }]);
+        let json = near_sdk::serde_json::to_string(&action).unwrap();
+        let stored = StoredAdminAction::from(action);
+        let bytes = stored.try_to_vec().unwrap();
+        let read = StoredAdminAction::try_from_slice(&bytes).unwrap();
+        assert_eq!(
+            near_sdk::serde_json::to_string(&AdminAction::from(&read)).unwrap(),
+            json
+        );
+    }
+}
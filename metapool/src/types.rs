@@ -1,12 +1,25 @@
-use near_sdk::json_types::{U128, U64};
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{Base58CryptoHash, U128, U64};
 use near_sdk::serde::{Deserialize, Serialize};
-use near_sdk::AccountId;
+use near_sdk::{AccountId, CryptoHash};
 use uint::construct_uint;
+use crate::utils::{apply_pct, exp_neg_e18, proportional};
 
 //----------------------------------------
 // CONSTANTS, types and interface structs
 //----------------------------------------
 
+/// current version of the contract state, see migrations.rs
+pub const STATE_VERSION: u16 = 2;
+
+/// current layout of the stored accounts, see impl BorshDeserialize for Account.
+/// Incremented on every Account layout change:
+/// 1: (state v1) untagged, with META fields realized_meta & lp_meter
+/// 2: (state v2) tagged, same fields as 1
+/// 3: (state v2) removes the META fields
+/// 4: (state v2) adds nslp_cost_basis
+pub const ACCOUNT_LAYOUT_VERSION: u16 = 4;
+
 // this contract token symbol
 pub const STNEAR: &str = "stNEAR";
 
@@ -43,6 +56,9 @@ pub const NUM_EPOCHS_TO_UNLOCK: EpochHeight = 4; //0 for testing in guild-net, 4
 /// The contract keeps at least 35 NEAR in the account to avoid being transferred out to cover
 /// contract code storage and some internal state.
 pub const MIN_BALANCE_FOR_STORAGE: u128 = 35_000_000_000_000_000_000_000_000;
+/// kept on top of the current storage cost when routing the gas rebate,
+/// so storage can grow until the next routing without LackBalanceForState
+pub const GAS_REBATE_STORAGE_BUFFER: u128 = FIVE_NEAR;
 /// if the remainder falls below this amount, rebalance is not performed
 pub const MIN_STAKE_UNSTAKE_AMOUNT_MOVEMENT: u128 = TEN_NEAR;
 
@@ -53,14 +69,81 @@ pub const DEFAULT_OPERATOR_SWAP_CUT_BASIS_POINTS: u16 = 300; // 3% swap fees go
 pub const DEFAULT_OPERATOR_REWARDS_FEE_BASIS_POINTS: u16 = 50; // 0.5% -- CANT BE HIGHER THAN 1000 / 10%
 
 //Note: License forbids you to change the following 3 constants and/or the developer's distribution mechanism
+// the developers' share is a fee beneficiary entry, set_fee_beneficiaries requires it at no less than these values
 pub const DEVELOPERS_ACCOUNT_ID: &str = "developers.near";
 pub const DEVELOPERS_REWARDS_FEE_BASIS_POINTS: u16 = 20; // 0.2% from rewards
 pub const DEVELOPERS_SWAP_CUT_BASIS_POINTS: u16 = 200; // 2% swap fees go to authors
 
-construct_uint! {
-    /// 256-bit unsigned integer.
-    pub struct U256(4);
+pub const MAX_FEE_BENEFICIARIES: usize = 8;
+
+/// NSLP snapshots kept for get_nslp_apr, one per epoch (ring buffer size)
+pub const NSLP_HISTORY_EPOCHS: u64 = 60;
+/// ~12hs epochs
+pub const EPOCHS_PER_YEAR: u128 = 730;
+
+/// NSLP fee curve limits
+pub const MAX_NSLP_FEE_BREAKPOINTS: usize = 8;
+pub const MAX_NSLP_FEE_CURVE_STEEPNESS: u8 = 20;
+
+/// discount of the NSLP stNEAR sold by buy_stnear, must be lower than nslp_min_discount_basis_points.
+/// Note: buy_stnear + delayed unstake earns the discount per unbonding period, paid by the LPs.
+/// The cap keeps it in the order of the staking rewards forgone while unbonding (~0.05% for 4 epochs at 9% APY)
+pub const DEFAULT_NSLP_BUY_DISCOUNT_BASIS_POINTS: u16 = 5; // 0.05%
+pub const MAX_NSLP_BUY_DISCOUNT_BASIS_POINTS: u16 = 5;
+pub const MAX_REWARDS_FEE_BASIS_POINTS: u16 = 1000; // sum of rewards fees, 10% cap
+
+/// lockup proxy contracts & lockup account suffixes registered at init/migration
+/// the owner can later add/remove entries (see owner.rs)
+pub const DEFAULT_LOCKUP_CONTRACTS: [&str; 2] = ["lockup-meta-pool.near", "lockup.meta-v2.pool.testnet"];
+pub const DEFAULT_LOCKUP_ACCOUNT_SUFFIXES: [&str; 2] = [".lockup.near", ".lockupy.testnet"];
+
+//-- pausable operations, bitmask stored in MetaPool.paused_operations
+/// deposit_and_stake & stake_for_lockup
+pub const PAUSE_DEPOSIT: u32 = 1 << 0;
+/// unstake, unstake_all & unstake_from_lockup_shares
+pub const PAUSE_DELAYED_UNSTAKE: u32 = 1 << 1;
+/// liquid_unstake & liquid_unstake_for_lockup
+pub const PAUSE_LIQUID_UNSTAKE: u32 = 1 << 2;
+/// nslp_add_liquidity
+pub const PAUSE_NSLP_ADD_LIQUIDITY: u32 = 1 << 3;
+/// nslp_remove_liquidity
+pub const PAUSE_NSLP_REMOVE_LIQUIDITY: u32 = 1 << 4;
+/// ft_transfer & ft_transfer_call
+pub const PAUSE_ST_NEAR_TRANSFER: u32 = 1 << 5;
+/// mt_transfer & mt_transfer_call of NSLP shares
+pub const PAUSE_NSLP_TRANSFER: u32 = 1 << 6;
+/// mt_transfer & mt_transfer_call of available NEAR
+pub const PAUSE_NEAR_TRANSFER: u32 = 1 << 7;
+/// buy_stnear
+pub const PAUSE_BUY_STNEAR: u32 = 1 << 8;
+pub const PAUSE_ALL_OPERATIONS: u32 = PAUSE_DEPOSIT
+    | PAUSE_DELAYED_UNSTAKE
+    | PAUSE_LIQUID_UNSTAKE
+    | PAUSE_NSLP_ADD_LIQUIDITY
+    | PAUSE_NSLP_REMOVE_LIQUIDITY
+    | PAUSE_ST_NEAR_TRANSFER
+    | PAUSE_NSLP_TRANSFER
+    | PAUSE_NEAR_TRANSFER
+    | PAUSE_BUY_STNEAR;
+
+//-- NEP-245 multi-token ids, see multi_token.rs
+/// internal NEAR balance (Account.available)
+pub const MT_TOKEN_NEAR: &str = "near";
+/// stNEAR (Account.stake_shares), same balance as the NEP-141 token
+pub const MT_TOKEN_STNEAR: &str = "stnear";
+/// NSLP shares (liquidity provider token)
+pub const MT_TOKEN_NSLP: &str = "nslp";
+
+// the code generated by construct_uint is not ours to lint
+#[allow(clippy::all)]
+mod u256 {
+    use super::construct_uint;
+    construct_uint! {
+        /// 256-bit unsigned integer.
+        pub struct U256(4);
+    }
 }
+pub use u256::U256;
 
 /// Raw type for duration in nanoseconds
 pub type Duration = u64;
@@ -128,9 +211,8 @@ pub struct GetAccountInfoResult {
     ///stNEAR owned valued in NEAR
     pub valued_st_near: U128, // st_near * stNEAR_price
 
-    //META owned (including pending rewards)
+    //META incentives were removed, always 0 (kept for wallets compat)
     pub meta: U128,
-    //realized META (without pending rewards)
     pub realized_meta: U128,
 
     /// The amount unstaked waiting for withdraw
@@ -198,8 +280,6 @@ pub struct GetContractStateResult {
     pub retrieved_for_unstake_claims: U128String, // new name
     pub reserve_for_unstake_claims: U128String, // old name, keep for backward comp
 
-    pub total_meta: U128String,
-
     pub accumulated_staked_rewards: U128String,
 
     pub nslp_liquidity: U128String,
@@ -220,20 +300,74 @@ pub struct GetContractStateResult {
 
     pub min_deposit_amount: U128String,
 
-    pub est_meta_rewards_stakers: U128String, //stakers
-    pub est_meta_rewards_lp: U128String,      //liquidity-providers
-    pub est_meta_rewards_lu: U128String,      //liquid-unstakers
-    pub max_meta_rewards_stakers: U128String, //stakers
-    pub max_meta_rewards_lp: U128String,      //liquidity-providers
-    pub max_meta_rewards_lu: U128String,      //liquid-unstakers
-
     pub unstaked_for_rebalance: U128String,
 }
 
+/// one accounting invariant, as evaluated by check_invariants
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct InvariantCheckJSON {
+    pub name: String,
+    /// the relation checked, e.g. "total_actually_staked == sum(sp.staked)"
+    pub expression: String,
+    pub left: U128String,
+    pub right: U128String,
+    pub ok: bool,
+}
+
+impl InvariantCheckJSON {
+    pub fn equal(name: &str, expression: &str, left: u128, right: u128) -> Self {
+        Self::new(name, expression, left, right, left == right)
+    }
+    pub fn at_least(name: &str, expression: &str, left: u128, right: u128) -> Self {
+        Self::new(name, expression, left, right, left >= right)
+    }
+    pub fn at_most(name: &str, expression: &str, left: u128, right: u128) -> Self {
+        Self::new(name, expression, left, right, left <= right)
+    }
+    fn new(name: &str, expression: &str, left: u128, right: u128, ok: bool) -> Self {
+        Self {
+            name: name.into(),
+            expression: expression.into(),
+            left: left.into(),
+            right: right.into(),
+            ok,
+        }
+    }
+}
+
+/// Struct returned from check_invariants
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct CheckInvariantsResult {
+    /// true if all checks passed
+    pub ok: bool,
+    pub checks: Vec<InvariantCheckJSON>,
+    /// names of the checks not evaluated because async calls are in-flight
+    pub skipped: Vec<String>,
+}
+
+/// Struct returned from check_account_invariants, sums over a page of accounts
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct AccountInvariantsJSON {
+    /// from_index for the next page, accounts_count when all the accounts were read
+    pub next_index: U64String,
+    pub accounts_count: U64String,
+    /// sum(accounts.available) for this page
+    pub sum_available: U128String,
+    /// sum(accounts.unstaked) for this page
+    pub sum_unstaked: U128String,
+    /// to compare with the sums of all the pages
+    pub total_available: U128String,
+    pub total_unstake_claims: U128String,
+}
+
 /// Struct returned from get_contract_params
 /// div-pool parameters info
 /// Represents contact parameters as JSON compatible struct
-#[derive(Serialize, Deserialize)]
+/// Note: not stored, queued changes are stored as StoredContractParamsV1
+#[derive(Serialize, Deserialize, Clone)]
 #[serde(crate = "near_sdk::serde")]
 pub struct ContractParamsJSON {
     ///NEAR/stNEAR Liquidity pool 1% fee target. If Liquidity=target, fee is 1%
@@ -243,14 +377,6 @@ pub struct ContractParamsJSON {
     ///NEAR/stNEAR Liquidity pool min fee
     pub nslp_min_discount_basis_points: u16, //0.1%
 
-    //The next 3 values define meta rewards multipliers %. (100 => 1x, 200 => 2x, ...)
-    ///for each stNEAR paid staking reward, reward stNEAR holders with g-stNEAR. default:5x. reward META = rewards * mult_pct / 100
-    pub staker_meta_mult_pct: u16,
-    ///for each stNEAR paid as discount, reward stNEAR sellers with g-stNEAR. default:1x. reward META = discounted * mult_pct / 100
-    pub stnear_sell_meta_mult_pct: u16,
-    ///for each stNEAR paid as discount, reward stNEAR sellers with g-stNEAR. default:20x. reward META = fee * mult_pct / 100
-    pub lp_provider_meta_mult_pct: u16,
-
     /// operator_fee_basis_points. 100 basis point => 1%. E.g.: owner_fee_basis_points=50 => 0.5% owner's fee
     pub operator_rewards_fee_basis_points: u16,
     /// operator_cut_basis_points.
@@ -261,6 +387,712 @@ pub struct ContractParamsJSON {
     pub min_stake_unstake_amount_movement: U128String,
 
     pub unstake_for_rebalance_cap_bp: u16,
+
+    /// NEAR/stNEAR Liquidity pool fee curve, None: keep the current curve
+    #[serde(default)]
+    pub nslp_fee_curve: Option<NslpFeeCurve>,
+
+    /// NEAR/stNEAR Liquidity target relative to total_for_staking, None: keep the current value.
+    /// While no relative target is set, the absolute nslp_liquidity_target is used
+    #[serde(default)]
+    pub nslp_relative_target: Option<NslpRelativeTarget>,
+    /// true: remove the relative target, going back to nslp_liquidity_target
+    #[serde(default)]
+    pub clear_nslp_relative_target: bool,
+
+    /// discount of the NSLP stNEAR sold by buy_stnear, None: keep the current value
+    #[serde(default)]
+    pub nslp_buy_discount_basis_points: Option<u16>,
+}
+
+/// NSLP liquidity target as basis points of total_for_staking,
+/// clamped to [floor, ceiling]. Recomputed every time the fee is quoted
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct NslpRelativeTarget {
+    pub basis_points: u16,
+    pub floor: U128String,
+    pub ceiling: U128String,
+}
+
+impl NslpRelativeTarget {
+    pub fn assert_valid(&self) {
+        assert!(
+            self.basis_points > 0 && self.basis_points <= 10_000,
+            "basis_points must be between 1 and 10000"
+        );
+        assert!(self.floor.0 <= self.ceiling.0, "floor must be <= ceiling");
+    }
+
+    pub fn target(&self, total_for_staking: u128) -> u128 {
+        std::cmp::min(
+            std::cmp::max(apply_pct(self.basis_points, total_for_staking), self.floor.0),
+            self.ceiling.0,
+        )
+    }
+}
+
+/// a point of a piecewise NSLP fee curve
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct NslpFeeBreakpoint {
+    /// NSLP liquidity after the swap, in basis points of nslp_liquidity_target
+    pub liquidity_bp: u16,
+    /// liquid unstake fee at that liquidity
+    pub discount_bp: u16,
+}
+
+/// shape of the liquid unstake fee, from nslp_max_discount_basis_points (empty pool)
+/// down to nslp_min_discount_basis_points (liquidity at or above nslp_liquidity_target)
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, PartialEq, Debug, Default)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(rename_all = "snake_case")]
+pub enum NslpFeeCurve {
+    /// straight line from max to min
+    #[default]
+    Linear,
+    /// straight lines between breakpoints, (0, max) and (10000, min) are implied
+    Piecewise(Vec<NslpFeeBreakpoint>),
+    /// fee = min + range * (e^(-k*x) - e^(-k)) / (1 - e^(-k)), x = liquidity/target.
+    /// The fee drops fast when liquidity is added to an almost empty pool
+    Exponential { steepness: u8 },
+}
+
+impl NslpFeeCurve {
+    pub fn assert_valid(&self, min_bp: u16, max_bp: u16) {
+        match self {
+            Self::Linear => {}
+            Self::Piecewise(breakpoints) => {
+                assert!(
+                    breakpoints.len() <= MAX_NSLP_FEE_BREAKPOINTS,
+                    "too many fee curve breakpoints"
+                );
+                let mut prev = NslpFeeBreakpoint {
+                    liquidity_bp: 0,
+                    discount_bp: max_bp,
+                };
+                for point in breakpoints {
+                    assert!(
+                        point.liquidity_bp > prev.liquidity_bp && point.liquidity_bp < 10_000,
+                        "breakpoints liquidity_bp must be increasing and between 0 and 10000"
+                    );
+                    assert!(
+                        point.discount_bp <= prev.discount_bp && point.discount_bp >= min_bp,
+                        "breakpoints discount_bp must be decreasing and between min and max"
+                    );
+                    prev = point.clone();
+                }
+            }
+            Self::Exponential { steepness } => assert!(
+                *steepness > 0 && *steepness <= MAX_NSLP_FEE_CURVE_STEEPNESS,
+                "steepness must be between 1 and {}",
+                MAX_NSLP_FEE_CURVE_STEEPNESS
+            ),
+        }
+    }
+
+    /// fee in basis points when the pool is left with near_after liquidity.
+    /// requires near_after < target
+    pub fn discount_bp(&self, near_after: u128, target: u128, min_bp: u16, max_bp: u16) -> u16 {
+        let range = (max_bp - min_bp) as u128;
+        match self {
+            Self::Linear => max_bp - proportional(range, near_after, target) as u16,
+            Self::Piecewise(breakpoints) => {
+                let mut points = vec![(0, max_bp)];
+                points.extend(breakpoints.iter().map(|x| (x.liquidity_bp, x.discount_bp)));
+                points.push((10_000, min_bp));
+                // near_after in the same units as target * liquidity_bp
+                let position = near_after * 10_000;
+                let inx = points
+                    .iter()
+                    .rposition(|(liquidity_bp, _)| target * (*liquidity_bp as u128) <= position)
+                    .unwrap_or(0);
+                let (l0, f0) = points[inx];
+                let (l1, f1) = points[inx + 1];
+                f0 - proportional(
+                    (f0 - f1) as u128,
+                    position - target * l0 as u128,
+                    target * (l1 - l0) as u128,
+                ) as u16
+            }
+            Self::Exponential { steepness } => {
+                const E18: u128 = 1_000_000_000_000_000_000;
+                let k = *steepness as u128;
+                let x = proportional(E18, near_after, target);
+                let e_kx = exp_neg_e18(k * x);
+                let e_k = exp_neg_e18(k * E18);
+                min_bp
+                    + proportional(range, e_kx.saturating_sub(e_k), E18 - e_k).min(range) as u16
+            }
+        }
+    }
+}
+
+/// a sampled point of the NSLP fee curve, see get_nslp_fee_curve
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct NslpFeeCurvePoint {
+    /// NSLP liquidity after the swap
+    pub liquidity: U128String,
+    pub discount_basis_points: u16,
+}
+
+/// Struct returned from get_nslp_fee_curve
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct NslpFeeCurveJSON {
+    pub curve: NslpFeeCurve,
+    pub liquidity_target: U128String,
+    pub points: Vec<NslpFeeCurvePoint>,
+}
+
+/// who receives a fee share. Operator & Treasury follow the current operator_account_id & treasury_account_id
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(rename_all = "snake_case")]
+pub enum FeeRecipient {
+    Operator,
+    Treasury,
+    Account(AccountId),
+}
+
+/// a fee beneficiary, see MetaPool.fee_beneficiaries
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FeeBeneficiary {
+    pub recipient: FeeRecipient,
+    /// share of the staking rewards, minted as stNEAR. 100 basis point => 1%
+    pub rewards_fee_bp: u16,
+    /// share of the liquid-unstake fee, in stNEAR. 100 basis point => 1% of the fee
+    pub swap_cut_bp: u16,
+}
+
+/// fees accrued by a fee beneficiary account, see MetaPool.fee_accruals
+#[derive(BorshDeserialize, BorshSerialize, Default)]
+pub struct FeeAccrual {
+    /// lifetime stNEAR minted from the rewards fee
+    pub rewards_fee_stnear: u128,
+    /// lifetime stNEAR received from liquid-unstake fee cuts
+    pub swap_cut_stnear: u128,
+    /// epoch of the last fee received
+    pub last_epoch: EpochHeight,
+    /// convert fee stNEAR into NEAR through delayed unstake each epoch
+    pub auto_unstake: bool,
+    /// fee stNEAR received since the last auto unstake
+    pub pending_auto_unstake: u128,
+    /// lifetime fee stNEAR auto unstaked
+    pub auto_unstaked_stnear: u128,
+}
+
+/// Struct returned from get_fee_report
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FeeReportJSON {
+    pub account_id: AccountId,
+    pub rewards_fee_stnear: U128String,
+    pub swap_cut_stnear: U128String,
+    pub last_epoch: U64String,
+    pub auto_unstake: bool,
+    pub pending_auto_unstake: U128String,
+    pub auto_unstaked_stnear: U128String,
+    /// current stNEAR balance (fees & other)
+    pub st_near_balance: U128String,
+}
+
+/// Access-control roles, see MetaPool.roles
+/// The owner implicitly holds every role, and operator_account_id implicitly holds Role::Operator
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    /// full admin rights, can grant & revoke roles. Held only by owner_account_id, can not be granted
+    Owner,
+    /// heartbeat bot: rebalance, manual stake, busy flags
+    Operator,
+    /// can pause operations during an incident
+    Guardian,
+    /// can change fees & NSLP params
+    FeeManager,
+    /// can add/remove staking pools and change their weights
+    PoolManager,
+    /// can change the treasury account
+    TreasuryManager,
+}
+
+//-- timelocked admin actions
+/// max configurable timelock delay, 30 days
+pub const MAX_TIMELOCK_DELAY_SECONDS: u32 = 30 * 24 * 60 * 60;
+
+/// classes of admin actions, each class has its own timelock delay
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(rename_all = "snake_case")]
+pub enum AdminActionClass {
+    ContractParams,
+    RewardFee,
+    StakingPools,
+    TreasuryAccount,
+    Upgrade,
+}
+
+/// timelock delay for each admin action class, in seconds.
+/// A delay of 0 means the direct set_xxx method can be used
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TimelockDelays {
+    pub contract_params: u32,
+    pub reward_fee: u32,
+    pub staking_pools: u32,
+    pub treasury_account: u32,
+    /// min delay between stage_upgrade and deploy_staged_upgrade
+    pub upgrade: u32,
+}
+
+impl Default for TimelockDelays {
+    fn default() -> Self {
+        Self {
+            contract_params: 24 * 60 * 60,       // 1 day
+            reward_fee: 3 * 24 * 60 * 60,        // 3 days
+            staking_pools: 12 * 60 * 60,         // 12 hours
+            treasury_account: 3 * 24 * 60 * 60, // 3 days
+            upgrade: 2 * 24 * 60 * 60,           // 2 days
+        }
+    }
+}
+
+impl TimelockDelays {
+    /// no delays, direct set_xxx methods enabled
+    pub fn none() -> Self {
+        Self {
+            contract_params: 0,
+            reward_fee: 0,
+            staking_pools: 0,
+            treasury_account: 0,
+            upgrade: 0,
+        }
+    }
+    pub fn get(&self, class: AdminActionClass) -> u32 {
+        match class {
+            AdminActionClass::ContractParams => self.contract_params,
+            AdminActionClass::RewardFee => self.reward_fee,
+            AdminActionClass::StakingPools => self.staking_pools,
+            AdminActionClass::TreasuryAccount => self.treasury_account,
+            AdminActionClass::Upgrade => self.upgrade,
+        }
+    }
+    pub fn set(&mut self, class: AdminActionClass, seconds: u32) {
+        match class {
+            AdminActionClass::ContractParams => self.contract_params = seconds,
+            AdminActionClass::RewardFee => self.reward_fee = seconds,
+            AdminActionClass::StakingPools => self.staking_pools = seconds,
+            AdminActionClass::TreasuryAccount => self.treasury_account = seconds,
+            AdminActionClass::Upgrade => self.upgrade = seconds,
+        }
+    }
+}
+
+/// Struct returned from get_state_version
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct StateVersionJSON {
+    /// version of the stored state
+    pub state_version: u16,
+    /// version of the state this code expects
+    pub code_state_version: u16,
+    /// all accounts are stored in this layout version or newer
+    pub accounts_version: u16,
+    /// account layout this code writes (ACCOUNT_LAYOUT_VERSION)
+    pub code_accounts_version: u16,
+    pub accounts_migration_index: U64String,
+    pub accounts_count: U64String,
+}
+
+/// Struct returned from migrate_accounts
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct AccountsMigrationJSON {
+    /// from_index for the next call
+    pub next_index: U64String,
+    /// accounts rewritten in this call
+    pub migrated: u32,
+    /// storage bytes released by the rewritten accounts
+    pub storage_reclaimed: U64String,
+    /// progress of the contiguous sweep
+    pub accounts_migration_index: U64String,
+    pub accounts_version: u16,
+}
+
+//-- staged upgrade
+/// code staged with stage_upgrade, only its hash is stored
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct StagedUpgrade {
+    /// sha256 of the wasm code
+    pub code_hash: CryptoHash,
+    pub staged_by: AccountId,
+    /// block timestamp (nanoseconds)
+    pub staged_at: u64,
+    /// earliest block timestamp (nanoseconds) for deploy_staged_upgrade
+    pub deployable_at: u64,
+}
+
+/// Struct returned from get_staged_upgrade
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct StagedUpgradeJSON {
+    pub code_hash: Base58CryptoHash,
+    pub staged_by: AccountId,
+    pub staged_at: U64String,
+    pub deployable_at: U64String,
+}
+
+//-- gas rebate
+/// the extra balance accumulated (30% of tx fees by near-protocol) is routed once per epoch, see route_gas_rebate
+/// basis points of the rebate for each destination, must sum 10000
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy)]
+#[serde(crate = "near_sdk::serde")]
+pub struct GasRebateSplit {
+    /// transferred to operator_account_id
+    pub operator_bp: u16,
+    /// transferred to treasury_account_id
+    pub treasury_bp: u16,
+    /// added to total_for_staking (and staked), increasing stNEAR price
+    pub staking_bp: u16,
+}
+
+impl Default for GasRebateSplit {
+    /// all to the operator, as transfer_extra_balance_accumulated did
+    fn default() -> Self {
+        Self {
+            operator_bp: 10_000,
+            treasury_bp: 0,
+            staking_bp: 0,
+        }
+    }
+}
+
+impl GasRebateSplit {
+    pub fn assert_valid(&self) {
+        assert!(
+            self.operator_bp as u32 + self.treasury_bp as u32 + self.staking_bp as u32 == 10_000,
+            "gas rebate split must sum 10000 basis points"
+        );
+    }
+}
+
+/// Struct returned from get_gas_rebate_info
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct GasRebateInfoJSON {
+    pub split: GasRebateSplit,
+    /// routable extra balance (storage cost & buffer excluded), routed at the next epoch if >= 1 NEAR
+    pub pending: U128String,
+    pub last_routed_epoch: U64String,
+    /// total routed since the counter was added
+    pub total_routed: U128String,
+}
+
+//-- proof of reserves
+/// a staking pool balance as reported by the pool, see verify_reserves
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PoolReserves {
+    pub account_id: AccountId,
+    /// get_account_total_balance answer from the pool (None: not verified yet or the call failed)
+    pub reported: Option<U128String>,
+    /// our internal accounting, sp.total_balance() when the answer was received
+    pub recorded: U128String,
+    /// the contract or the pool had async calls in flight when the answer was received,
+    /// the pool is not verified (ok=false), run the round again
+    pub busy: bool,
+    /// reported >= recorded (the difference are rewards not yet booked)
+    pub ok: bool,
+}
+
+/// reserves snapshot, built in batches by verify_reserves
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ReservesSnapshot {
+    /// account that started the verification round
+    pub verified_by: AccountId,
+    /// block timestamp (nanoseconds) of verify_reserves(from_index=0)
+    pub started_at: U64String,
+    /// block timestamp (nanoseconds) when the last pool was verified, None while in progress
+    pub completed_at: Option<U64String>,
+    /// one entry per staking pool, same order as staking_pools
+    pub pools: Vec<PoolReserves>,
+    /// sum(pools.reported)
+    pub pools_total: U128String,
+    /// env::account_balance() at completion
+    pub contract_balance: U128String,
+    /// included in contract_balance, informative
+    pub nslp_liquidity: U128String,
+    pub retrieved_for_unstake_claims: U128String,
+    /// pools_total + contract_balance
+    pub total_reserves: U128String,
+    /// total_for_staking + total_unstake_claims + total_available
+    pub total_liabilities: U128String,
+    /// all pools ok and total_reserves >= total_liabilities
+    pub ok: bool,
+}
+
+//-- two-step transfer of key accounts (owner, operator, treasury)
+/// a proposal not accepted in 7 days expires
+pub const ACCOUNT_TRANSFER_EXPIRY_NANOSECONDS: u64 = 7 * 24 * 60 * 60 * 1_000_000_000;
+
+/// a key account change, takes effect when the proposed account calls accept_xxx
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PendingAccountTransfer {
+    pub account_id: AccountId,
+    pub proposed_by: AccountId,
+    /// block timestamp (nanoseconds) after which the proposal can not be accepted
+    pub expires_at: U64String,
+}
+
+/// Struct returned from get_pending_account_transfers
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PendingAccountTransfersJSON {
+    pub owner: Option<PendingAccountTransfer>,
+    pub operator: Option<PendingAccountTransfer>,
+    pub treasury: Option<PendingAccountTransfer>,
+}
+
+/// a queued admin action, executed by execute_admin_action after its timelock delay
+/// Note: JSON only, stored as StoredAdminAction
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(rename_all = "snake_case")]
+pub enum AdminAction {
+    SetContractParams(ContractParamsJSON),
+    SetRewardFee(u16),
+    SetStakingPools(Vec<StakingPoolArgItem>),
+    SetTreasuryAccountId(AccountId),
+    /// lowering a delay must wait the current delay of that class
+    SetTimelockDelay {
+        class: AdminActionClass,
+        seconds: u32,
+    },
+    SetGasRebateSplit(GasRebateSplit),
+    SetFeeBeneficiaries(Vec<FeeBeneficiary>),
+}
+
+impl AdminAction {
+    /// the class determines the timelock delay
+    pub fn class(&self) -> AdminActionClass {
+        match self {
+            AdminAction::SetContractParams(_) => AdminActionClass::ContractParams,
+            AdminAction::SetRewardFee(_) => AdminActionClass::RewardFee,
+            AdminAction::SetStakingPools(_) => AdminActionClass::StakingPools,
+            AdminAction::SetTreasuryAccountId(_) => AdminActionClass::TreasuryAccount,
+            AdminAction::SetTimelockDelay { class, .. } => *class,
+            AdminAction::SetGasRebateSplit(_) => AdminActionClass::RewardFee,
+            AdminAction::SetFeeBeneficiaries(_) => AdminActionClass::RewardFee,
+        }
+    }
+    /// role required to queue & execute the action
+    pub fn role(&self) -> Role {
+        match self {
+            AdminAction::SetContractParams(_) => Role::FeeManager,
+            AdminAction::SetRewardFee(_) => Role::FeeManager,
+            AdminAction::SetStakingPools(_) => Role::PoolManager,
+            AdminAction::SetTreasuryAccountId(_) => Role::TreasuryManager,
+            AdminAction::SetTimelockDelay { .. } => Role::Owner,
+            AdminAction::SetGasRebateSplit(_) => Role::FeeManager,
+            AdminAction::SetFeeBeneficiaries(_) => Role::FeeManager,
+        }
+    }
+}
+
+/// Struct returned from get_pending_admin_actions
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PendingAdminAction {
+    pub id: u32,
+    pub action: AdminAction,
+    pub queued_by: AccountId,
+    /// block timestamp (nanoseconds) when queued
+    pub queued_at: U64String,
+    /// earliest block timestamp (nanoseconds) for execute_admin_action
+    pub executable_at: U64String,
+}
+
+//-- storage layout of queued admin actions, see MetaPool.pending_admin_actions
+// The JSON argument structs change with the contract API, so queued actions are converted
+// to these structs when queued. Never modify a stored variant or struct:
+// a layout change adds a new variant (e.g. SetContractParamsV2), and the old one
+// keeps converting into the current AdminAction, so actions queued before an upgrade still execute
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct StoredPendingAdminAction {
+    pub id: u32,
+    pub action: StoredAdminAction,
+    pub queued_by: AccountId,
+    pub queued_at: u64,
+    pub executable_at: u64,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub enum StoredAdminAction {
+    SetContractParamsV1(StoredContractParamsV1),
+    SetRewardFeeV1(u16),
+    SetStakingPoolsV1(Vec<(AccountId, u16)>),
+    SetTreasuryAccountIdV1(AccountId),
+    SetTimelockDelayV1 { class: AdminActionClass, seconds: u32 },
+    SetGasRebateSplitV1 { operator_bp: u16, treasury_bp: u16, staking_bp: u16 },
+    SetFeeBeneficiariesV1(Vec<(FeeRecipient, u16, u16)>),
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct StoredContractParamsV1 {
+    pub nslp_liquidity_target: u128,
+    pub nslp_max_discount_basis_points: u16,
+    pub nslp_min_discount_basis_points: u16,
+    pub operator_rewards_fee_basis_points: u16,
+    pub operator_swap_cut_basis_points: u16,
+    pub treasury_swap_cut_basis_points: u16,
+    pub min_deposit_amount: u128,
+    pub min_stake_unstake_amount_movement: u128,
+    pub unstake_for_rebalance_cap_bp: u16,
+    pub nslp_fee_curve: Option<NslpFeeCurve>,
+    pub nslp_relative_target: Option<NslpRelativeTarget>,
+    pub clear_nslp_relative_target: bool,
+    pub nslp_buy_discount_basis_points: Option<u16>,
+}
+
+impl From<AdminAction> for StoredAdminAction {
+    fn from(action: AdminAction) -> Self {
+        match action {
+            AdminAction::SetContractParams(params) => {
+                Self::SetContractParamsV1(StoredContractParamsV1 {
+                    nslp_liquidity_target: params.nslp_liquidity_target.0,
+                    nslp_max_discount_basis_points: params.nslp_max_discount_basis_points,
+                    nslp_min_discount_basis_points: params.nslp_min_discount_basis_points,
+                    operator_rewards_fee_basis_points: params.operator_rewards_fee_basis_points,
+                    operator_swap_cut_basis_points: params.operator_swap_cut_basis_points,
+                    treasury_swap_cut_basis_points: params.treasury_swap_cut_basis_points,
+                    min_deposit_amount: params.min_deposit_amount.0,
+                    min_stake_unstake_amount_movement: params.min_stake_unstake_amount_movement.0,
+                    unstake_for_rebalance_cap_bp: params.unstake_for_rebalance_cap_bp,
+                    nslp_fee_curve: params.nslp_fee_curve,
+                    nslp_relative_target: params.nslp_relative_target,
+                    clear_nslp_relative_target: params.clear_nslp_relative_target,
+                    nslp_buy_discount_basis_points: params.nslp_buy_discount_basis_points,
+                })
+            }
+            AdminAction::SetRewardFee(basis_points) => Self::SetRewardFeeV1(basis_points),
+            AdminAction::SetStakingPools(list) => Self::SetStakingPoolsV1(
+                list.into_iter()
+                    .map(|x| (x.account_id, x.weight_basis_points))
+                    .collect(),
+            ),
+            AdminAction::SetTreasuryAccountId(account_id) => {
+                Self::SetTreasuryAccountIdV1(account_id)
+            }
+            AdminAction::SetTimelockDelay { class, seconds } => {
+                Self::SetTimelockDelayV1 { class, seconds }
+            }
+            AdminAction::SetGasRebateSplit(split) => Self::SetGasRebateSplitV1 {
+                operator_bp: split.operator_bp,
+                treasury_bp: split.treasury_bp,
+                staking_bp: split.staking_bp,
+            },
+            AdminAction::SetFeeBeneficiaries(list) => Self::SetFeeBeneficiariesV1(
+                list.into_iter()
+                    .map(|x| (x.recipient, x.rewards_fee_bp, x.swap_cut_bp))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+impl From<&StoredAdminAction> for AdminAction {
+    fn from(stored: &StoredAdminAction) -> Self {
+        match stored {
+            StoredAdminAction::SetContractParamsV1(params) => {
+                Self::SetContractParams(ContractParamsJSON {
+                    nslp_liquidity_target: params.nslp_liquidity_target.into(),
+                    nslp_max_discount_basis_points: params.nslp_max_discount_basis_points,
+                    nslp_min_discount_basis_points: params.nslp_min_discount_basis_points,
+                    operator_rewards_fee_basis_points: params.operator_rewards_fee_basis_points,
+                    operator_swap_cut_basis_points: params.operator_swap_cut_basis_points,
+                    treasury_swap_cut_basis_points: params.treasury_swap_cut_basis_points,
+                    min_deposit_amount: params.min_deposit_amount.into(),
+                    min_stake_unstake_amount_movement: params
+                        .min_stake_unstake_amount_movement
+                        .into(),
+                    unstake_for_rebalance_cap_bp: params.unstake_for_rebalance_cap_bp,
+                    nslp_fee_curve: params.nslp_fee_curve.clone(),
+                    nslp_relative_target: params.nslp_relative_target.clone(),
+                    clear_nslp_relative_target: params.clear_nslp_relative_target,
+                    nslp_buy_discount_basis_points: params.nslp_buy_discount_basis_points,
+                })
+            }
+            StoredAdminAction::SetRewardFeeV1(basis_points) => Self::SetRewardFee(*basis_points),
+            StoredAdminAction::SetStakingPoolsV1(list) => Self::SetStakingPools(
+                list.iter()
+                    .map(|(account_id, weight_basis_points)| StakingPoolArgItem {
+                        account_id: account_id.clone(),
+                        weight_basis_points: *weight_basis_points,
+                    })
+                    .collect(),
+            ),
+            StoredAdminAction::SetTreasuryAccountIdV1(account_id) => {
+                Self::SetTreasuryAccountId(account_id.clone())
+            }
+            StoredAdminAction::SetTimelockDelayV1 { class, seconds } => Self::SetTimelockDelay {
+                class: *class,
+                seconds: *seconds,
+            },
+            StoredAdminAction::SetGasRebateSplitV1 {
+                operator_bp,
+                treasury_bp,
+                staking_bp,
+            } => Self::SetGasRebateSplit(GasRebateSplit {
+                operator_bp: *operator_bp,
+                treasury_bp: *treasury_bp,
+                staking_bp: *staking_bp,
+            }),
+            StoredAdminAction::SetFeeBeneficiariesV1(list) => Self::SetFeeBeneficiaries(
+                list.iter()
+                    .map(|(recipient, rewards_fee_bp, swap_cut_bp)| FeeBeneficiary {
+                        recipient: recipient.clone(),
+                        rewards_fee_bp: *rewards_fee_bp,
+                        swap_cut_bp: *swap_cut_bp,
+                    })
+                    .collect(),
+            ),
+        }
+    }
+}
+
+impl From<&StoredPendingAdminAction> for PendingAdminAction {
+    fn from(stored: &StoredPendingAdminAction) -> Self {
+        Self {
+            id: stored.id,
+            action: AdminAction::from(&stored.action),
+            queued_by: stored.queued_by.clone(),
+            queued_at: stored.queued_at.into(),
+            executable_at: stored.executable_at.into(),
+        }
+    }
+}
+
+/// Struct returned from get_paused_operations
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PausedOperationsJSON {
+    /// raw bitmask, see PAUSE_* constants
+    pub paused_operations: u32,
+    pub deposit: bool,
+    pub delayed_unstake: bool,
+    pub liquid_unstake: bool,
+    pub nslp_add_liquidity: bool,
+    pub nslp_remove_liquidity: bool,
+    pub st_near_transfer: bool,
+    pub nslp_transfer: bool,
+    pub near_transfer: bool,
+    pub buy_stnear: bool,
 }
 
 #[derive(Serialize)]
@@ -270,6 +1102,85 @@ pub struct RemoveLiquidityResult {
     pub st_near: U128String,
 }
 
+/// NSLP state at the start of an epoch, see get_nslp_apr
+#[derive(BorshDeserialize, BorshSerialize, Clone)]
+pub struct NslpEpochSnapshot {
+    pub epoch: EpochHeight,
+    /// NEAR value of one nslp share (1e24)
+    pub share_price: u128,
+    /// MetaPool.nslp_total_fees_retained at that time
+    pub total_fees_retained: u128,
+}
+
+/// Struct returned from get_lp_position
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct LpPositionJSON {
+    pub account_id: AccountId,
+    pub nslp_shares: U128String,
+    /// current NEAR value of the shares
+    pub value: U128String,
+    /// NEAR added for the current shares (None: position opened before it was tracked)
+    pub cost_basis: Option<U128String>,
+    /// value - cost_basis: liquid-unstake fees retained by the NSLP & rewards of the NSLP stNEAR
+    pub earned: Option<U128String>,
+}
+
+/// Struct returned from get_nslp_apr
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct NslpAprJSON {
+    /// epochs actually measured, can be less than requested if there's not enough history
+    pub epochs: U64String,
+    /// annualized nslp share price growth
+    pub apr_basis_points: u32,
+    /// liquid-unstake fees retained by the NSLP in the measured epochs
+    pub fees_retained: U128String,
+    /// liquid-unstake fees retained by the NSLP since tracking started
+    pub total_fees_retained: U128String,
+}
+
+/// Struct returned from buy_stnear
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct BuyStNearResult {
+    /// total stNEAR received
+    pub st_near: U128String,
+    /// part of st_near bought from the NSLP
+    pub from_nslp: U128String,
+    /// NEAR left in the account available balance, when the part to mint is below the min deposit
+    pub near_available: U128String,
+}
+
+/// Struct returned from get_buy_stnear_quote
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct BuyStNearQuoteJSON {
+    /// stNEAR received by deposit_and_stake
+    pub mint_st_near: U128String,
+    /// stNEAR received by buy_stnear
+    pub buy_st_near: U128String,
+    /// part of buy_st_near bought from the NSLP
+    pub from_nslp: U128String,
+    /// NEAR paid to the NSLP
+    pub near_to_nslp: U128String,
+    /// NEAR left available (not minted), see BuyStNearResult
+    pub near_available: U128String,
+    pub discount_basis_points: u16,
+}
+
+/// Struct returned from nslp_remove_liquidity_near_only
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct RemoveLiquidityNearOnlyResult {
+    /// NEAR transferred now
+    pub near: U128String,
+    /// NEAR value of the stNEAR part, delayed-unstaked for the LP
+    pub unstaked: U128String,
+    /// epoch when the unstaked part can be withdrawn
+    pub unlock_epoch: U64String,
+}
+
 #[derive(Serialize)]
 #[serde(crate = "near_sdk::serde")]
 pub struct LiquidUnstakeResult {
@@ -294,7 +1205,7 @@ pub struct StakingPoolJSONInfo {
 }
 
 /// struct used as parameter for set_staking_pools
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 #[serde(crate = "near_sdk::serde")]
 pub struct StakingPoolArgItem {
     pub account_id: AccountId, 
@@ -307,3 +1218,100 @@ pub struct VLoanRequest {
     //total requested
     pub amount_requested: u128,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MIN_BP: u16 = 25;
+    const MAX_BP: u16 = 180;
+    const TARGET: u128 = 10_000 * NEAR;
+
+    /// fee with `liquidity_bp` of the target left in the pool
+    fn discount_at(curve: &NslpFeeCurve, liquidity_bp: u128) -> u16 {
+        curve.discount_bp(TARGET * liquidity_bp / 10_000, TARGET, MIN_BP, MAX_BP)
+    }
+
+    fn piecewise() -> NslpFeeCurve {
+        NslpFeeCurve::Piecewise(vec![NslpFeeBreakpoint {
+            liquidity_bp: 5000,
+            discount_bp: 50,
+        }])
+    }
+
+    #[test]
+    fn test_linear_curve() {
+        let curve = NslpFeeCurve::Linear;
+        assert_eq!(discount_at(&curve, 0), MAX_BP);
+        assert_eq!(discount_at(&curve, 5000), 103);
+        assert_eq!(discount_at(&curve, 10_000), MIN_BP);
+    }
+
+    #[test]
+    fn test_piecewise_curve() {
+        let curve = piecewise();
+        curve.assert_valid(MIN_BP, MAX_BP);
+        assert_eq!(discount_at(&curve, 0), MAX_BP);
+        assert_eq!(discount_at(&curve, 2500), 115);
+        assert_eq!(discount_at(&curve, 5000), 50);
+        assert_eq!(discount_at(&curve, 7500), 38);
+    }
+
+    #[test]
+    fn test_piecewise_without_breakpoints_is_linear() {
+        let curve = NslpFeeCurve::Piecewise(vec![]);
+        for liquidity_bp in (0..10_000).step_by(1250) {
+            assert_eq!(
+                discount_at(&curve, liquidity_bp),
+                discount_at(&NslpFeeCurve::Linear, liquidity_bp)
+            );
+        }
+    }
+
+    #[test]
+    fn test_exponential_curve() {
+        let curve = NslpFeeCurve::Exponential { steepness: 5 };
+        curve.assert_valid(MIN_BP, MAX_BP);
+        assert_eq!(discount_at(&curve, 0), MAX_BP);
+        assert!(discount_at(&curve, 5000) < discount_at(&NslpFeeCurve::Linear, 5000));
+        let mut prev = MAX_BP;
+        for liquidity_bp in (500..10_000).step_by(500) {
+            let discount = discount_at(&curve, liquidity_bp);
+            assert!(discount <= prev && discount >= MIN_BP);
+            prev = discount;
+        }
+        assert_eq!(discount_at(&curve, 9900), MIN_BP);
+    }
+
+    #[test]
+    #[should_panic(expected = "breakpoints liquidity_bp must be increasing")]
+    fn test_piecewise_unordered_breakpoints() {
+        NslpFeeCurve::Piecewise(vec![
+            NslpFeeBreakpoint {
+                liquidity_bp: 5000,
+                discount_bp: 50,
+            },
+            NslpFeeBreakpoint {
+                liquidity_bp: 4000,
+                discount_bp: 40,
+            },
+        ])
+        .assert_valid(MIN_BP, MAX_BP);
+    }
+
+    #[test]
+    #[should_panic(expected = "breakpoints discount_bp must be decreasing")]
+    fn test_piecewise_breakpoint_below_min() {
+        NslpFeeCurve::Piecewise(vec![NslpFeeBreakpoint {
+            liquidity_bp: 5000,
+            discount_bp: MIN_BP - 1,
+        }])
+        .assert_valid(MIN_BP, MAX_BP);
+    }
+
+    #[test]
+    #[should_panic(expected = "steepness must be between")]
+    fn test_exponential_zero_steepness() {
+        NslpFeeCurve::Exponential { steepness: 0 }.assert_valid(MIN_BP, MAX_BP);
+    }
+}
@@ -1,12 +1,5 @@
 pub use crate::types::*;
-use near_sdk::{env, PromiseResult};
-
-#[macro_export]
-macro_rules! event {
-    ($($arg:tt)*) => ({
-        env::log(format!($($arg)*).as_bytes());
-    });
-}
+use near_sdk::{env, AccountId, PromiseResult};
 
 #[macro_export]
 #[cfg(debug_log)]
@@ -37,25 +30,61 @@ pub fn assert_one_yocto() {
     );
 }
 
-pub fn assert_lockup_contract_calling() {
+/// nslp_relative_target & clear_nslp_relative_target of a set_contract_params
+pub fn assert_nslp_relative_target_params(params: &ContractParamsJSON) {
+    if let Some(relative) = &params.nslp_relative_target {
+        assert!(
+            !params.clear_nslp_relative_target,
+            "can not set and clear nslp_relative_target at the same time"
+        );
+        relative.assert_valid();
+    }
+}
+
+/// nslp_buy_discount_basis_points limits, see MAX_NSLP_BUY_DISCOUNT_BASIS_POINTS
+pub fn assert_nslp_buy_discount_valid(discount_bp: u16, min_discount_bp: u16) {
+    assert!(
+        discount_bp <= MAX_NSLP_BUY_DISCOUNT_BASIS_POINTS,
+        "nslp_buy_discount_basis_points max is {}",
+        MAX_NSLP_BUY_DISCOUNT_BASIS_POINTS
+    );
+    // buying below the liquid unstake fee would allow a liquid_unstake+buy_stnear arbitrage
     assert!(
-        env::predecessor_account_id() == "lockup-meta-pool.near"
-        || env::predecessor_account_id() == "lockup.meta-v2.pool.testnet",
-        "the function can only be operated by lockup-meta-pool.near"
+        discount_bp < min_discount_bp,
+        "nslp_buy_discount_basis_points must be lower than nslp_min_discount_basis_points"
     );
 }
 
-/// verify if it a lockup account
-pub fn is_lockup_account(account_id: &String) -> bool{
-    account_id.ends_with(".lockup.near") 
-    || account_id.ends_with(".lockupy.testnet") 
+/// initial registry of lockup proxy contracts (see MetaPool.lockup_contracts)
+pub fn default_lockup_contracts() -> Vec<AccountId> {
+    DEFAULT_LOCKUP_CONTRACTS.iter().map(|x| x.to_string()).collect()
 }
 
-/// assert it is not a lockup account
-pub fn assert_not_lockup_account_calling() {
-    assert!(!is_lockup_account(&env::predecessor_account_id()),"a .lockup.near account can not be used here");
+/// initial registry of lockup account suffixes (see MetaPool.lockup_account_suffixes)
+pub fn default_lockup_account_suffixes() -> Vec<String> {
+    DEFAULT_LOCKUP_ACCOUNT_SUFFIXES.iter().map(|x| x.to_string()).collect()
 }
 
+/// initial fee beneficiaries (see MetaPool.fee_beneficiaries)
+pub fn default_fee_beneficiaries() -> Vec<FeeBeneficiary> {
+    vec![
+        FeeBeneficiary {
+            recipient: FeeRecipient::Operator,
+            rewards_fee_bp: DEFAULT_OPERATOR_REWARDS_FEE_BASIS_POINTS,
+            swap_cut_bp: DEFAULT_OPERATOR_SWAP_CUT_BASIS_POINTS,
+        },
+        FeeBeneficiary {
+            recipient: FeeRecipient::Treasury,
+            rewards_fee_bp: 0,
+            swap_cut_bp: DEFAULT_TREASURY_SWAP_CUT_BASIS_POINTS,
+        },
+        FeeBeneficiary {
+            recipient: FeeRecipient::Account(DEVELOPERS_ACCOUNT_ID.into()),
+            rewards_fee_bp: DEVELOPERS_REWARDS_FEE_BASIS_POINTS,
+            swap_cut_bp: DEVELOPERS_SWAP_CUT_BASIS_POINTS,
+        },
+    ]
+}
 
 pub fn is_promise_success() -> bool {
     assert_eq!(
@@ -63,10 +92,7 @@ pub fn is_promise_success() -> bool {
         1,
         "Contract expected a result on the callback"
     );
-    match env::promise_result(0) {
-        PromiseResult::Successful(_) => true,
-        _ => false,
-    }
+    matches!(env::promise_result(0), PromiseResult::Successful(_))
 }
 
 pub fn apply_pct(basis_points: u16, amount: u128) -> u128 {
@@ -78,6 +104,31 @@ pub fn apply_multiplier(amount: u128, percentage: u16) -> u128 {
 
 //-- SHARED COMPUTATIONS
 
+/// e^(-x), x and the result in 1e18 fixed point
+/// computed as (e^(-x/2^m))^(2^m), with a taylor series for the small exponent
+pub fn exp_neg_e18(x: u128) -> u128 {
+    const E18: u128 = 1_000_000_000_000_000_000;
+    let mut m = 0;
+    while (x >> m) > E18 / 16 {
+        m += 1;
+    }
+    let y = x >> m;
+    let mut term = E18;
+    let mut result = E18;
+    for n in 1..=6 {
+        term = term * y / E18 / n;
+        if n % 2 == 1 {
+            result -= term;
+        } else {
+            result += term;
+        }
+    }
+    for _ in 0..m {
+        result = result * result / E18;
+    }
+    result
+}
+
 /// returns amount * numerator/denominator
 pub fn proportional(amount: u128, numerator: u128, denominator: u128) -> u128 {
     return (U256::from(amount) * U256::from(numerator) / U256::from(denominator)).as_u128();
@@ -124,3 +175,34 @@ pub fn amount_from_shares(num_shares: u128, total_amount: u128, total_shares: u1
 pub fn is_close(requested: u128, total: u128) -> bool {
     requested >= total.saturating_sub(ONE_MILLI_NEAR) && requested <= total + ONE_MILLI_NEAR
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const E18: u128 = 1_000_000_000_000_000_000;
+
+    fn assert_close_e18(value: u128, expected: u128) {
+        let diff = value.abs_diff(expected);
+        assert!(diff < 100_000_000, "{} is not close to {}", value, expected);
+    }
+
+    #[test]
+    fn test_exp_neg_e18() {
+        assert_eq!(exp_neg_e18(0), E18);
+        assert_close_e18(exp_neg_e18(E18 / 2), 606_530_659_712_633_423);
+        assert_close_e18(exp_neg_e18(E18), 367_879_441_171_442_321);
+        assert_close_e18(exp_neg_e18(2 * E18), 135_335_283_236_612_691);
+        assert!(exp_neg_e18(40 * E18) < 100);
+    }
+
+    #[test]
+    fn test_exp_neg_e18_decreasing() {
+        let mut prev = exp_neg_e18(0);
+        for inx in 1..=80 {
+            let value = exp_neg_e18(inx * E18 / 4);
+            assert!(value < prev, "e^-{}/4 is not decreasing", inx);
+            prev = value;
+        }
+    }
+}
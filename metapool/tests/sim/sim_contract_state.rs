@@ -105,7 +105,8 @@ pub fn set_unstake_for_rebalance_cap_bp(bp:u16, sim:&Simulation){
     contract_params["unstake_for_rebalance_cap_bp"] = bp.into();
     let mut args = json!({ "params":{} });
     args["params"] = contract_params;
-    let res = sim.operator.call(
+    // set_contract_params requires Role::FeeManager (the owner holds every role)
+    let res = sim.owner.call(
         sim.metapool.account_id(),
         "set_contract_params",
         args.to_string().as_bytes(),
@@ -138,7 +139,8 @@ pub fn set_staking_pools_weight(weights_bp: Vec<u16>, sim:&Simulation){
     
 pub fn set_staking_pools(pools: Vec<StakingPoolArgItem>, sim:&Simulation){
     let metapool_contract = &sim.metapool;
-    let res = call!(sim.operator,
+    // set_staking_pools requires Role::PoolManager (the owner holds every role)
+    let res = call!(sim.owner,
         metapool_contract.set_staking_pools(pools),
         1,
         125 * TGAS